@@ -0,0 +1,637 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+
+use arrow::array::types::{Float64Type, Int64Type};
+use arrow::array::{
+    Array, ArrayRef, ArrowNativeTypeOp, ArrowNumericType, AsArray, Float64Array, Int64Array,
+    PrimitiveArray, StringArray,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::row::encode_symbols;
+use crate::{
+    Db, EpochDay, Error, SYMBOL_COL, TIMESTAMP_COL, TIMEZONE_METADATA, Table, WriteMode,
+    key_columns, timestamps,
+};
+
+/// An aggregate function computed per (symbol, bucket) group by [`Db::aggregate`].
+///
+/// Nulls are ignored; a group with no non-null values yields null (or 0 for
+/// `Count`). `Min`, `Max`, `Sum` and `Mean` require an Int64 or Float64 column,
+/// and a `Sum` that overflows an Int64 is an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggFn {
+    Min,
+    Max,
+    Sum,
+    Count,
+    Mean,
+    First,
+    Last,
+}
+
+impl AggFn {
+    fn name(self) -> &'static str {
+        match self {
+            AggFn::Min => "min",
+            AggFn::Max => "max",
+            AggFn::Sum => "sum",
+            AggFn::Count => "count",
+            AggFn::Mean => "mean",
+            AggFn::First => "first",
+            AggFn::Last => "last",
+        }
+    }
+}
+
+/// One output column of [`Db::aggregate`]: `func` applied to `column`, named
+/// `<column>_<func>` (e.g. `price_max`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aggregation {
+    pub column: String,
+    pub func: AggFn,
+}
+
+impl Aggregation {
+    pub fn new(column: impl Into<String>, func: AggFn) -> Self {
+        Self {
+            column: column.into(),
+            func,
+        }
+    }
+
+    fn output_name(&self) -> String {
+        format!("{}_{}", self.column, self.func.name())
+    }
+}
+
+/// The rows of one (symbol, bucket) group, as runs within partitions. For
+/// [`Db::rolling`], `bucket` is the probe timestamp.
+struct Group<'a> {
+    symbol: &'a str,
+    bucket: i64,
+    segments: Vec<(EpochDay, Range<usize>)>,
+}
+
+struct Bar {
+    bucket: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+}
+
+impl Db {
+    /// Buckets `symbol`'s rows with timestamps in `range` into fixed bars of
+    /// `interval_us` microseconds, computing open/high/low/close of `price_col`
+    /// and the sum of `volume_col`.
+    ///
+    /// Bars are aligned to multiples of `interval_us` since the epoch and labelled
+    /// by their start time in the `timestamp` column. Empty bars are omitted, as
+    /// are rows where either column is null. Both columns must be Float64.
+    pub fn ohlcv(
+        &self,
+        table: &str,
+        symbol: &str,
+        range: Range<i64>,
+        interval_us: i64,
+        price_col: &str,
+        volume_col: &str,
+    ) -> Result<RecordBatch, Error> {
+        if interval_us <= 0 {
+            return Err(Error::InvalidInterval(interval_us));
+        }
+        let table = self.table(table)?;
+        let price_idx = table.f64_column_index(price_col)?;
+        let volume_idx = table.f64_column_index(volume_col)?;
+
+        let mut bars: Vec<Bar> = Vec::new();
+        for (_, part, rows) in table.scan(symbol, range) {
+            let ts = part.timestamps();
+            let price = part.batch.column(price_idx).as_primitive::<Float64Type>();
+            let volume = part.batch.column(volume_idx).as_primitive::<Float64Type>();
+            for i in rows {
+                if price.is_null(i) || volume.is_null(i) {
+                    continue;
+                }
+                let bucket = ts[i].div_euclid(interval_us) * interval_us;
+                let (p, v) = (price.value(i), volume.value(i));
+                match bars.last_mut() {
+                    Some(bar) if bar.bucket == bucket => {
+                        bar.high = bar.high.max(p);
+                        bar.low = bar.low.min(p);
+                        bar.close = p;
+                        bar.volume += v;
+                    }
+                    _ => bars.push(Bar {
+                        bucket,
+                        open: p,
+                        high: p,
+                        low: p,
+                        close: p,
+                        volume: v,
+                    }),
+                }
+            }
+        }
+
+        let schema: SchemaRef = Arc::new(Schema::new(vec![
+            Field::new(TIMESTAMP_COL, DataType::Int64, false),
+            Field::new("open", DataType::Float64, false),
+            Field::new("high", DataType::Float64, false),
+            Field::new("low", DataType::Float64, false),
+            Field::new("close", DataType::Float64, false),
+            Field::new("volume", DataType::Float64, false),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(bars.iter().map(|b| b.bucket))),
+                Arc::new(Float64Array::from_iter_values(bars.iter().map(|b| b.open))),
+                Arc::new(Float64Array::from_iter_values(bars.iter().map(|b| b.high))),
+                Arc::new(Float64Array::from_iter_values(bars.iter().map(|b| b.low))),
+                Arc::new(Float64Array::from_iter_values(bars.iter().map(|b| b.close))),
+                Arc::new(Float64Array::from_iter_values(
+                    bars.iter().map(|b| b.volume),
+                )),
+            ],
+        )?)
+    }
+
+    /// Computes the volume-weighted average of `price_col` per `interval_us`
+    /// bucket over `symbol`'s rows in `range`, weighting by `size_col`.
+    ///
+    /// Returns `timestamp` (bucket start) and `vwap` columns. Empty buckets are
+    /// omitted; a bucket whose sizes sum to zero yields a null `vwap`. Rows where
+    /// either column is null are skipped. Both columns must be Float64.
+    pub fn vwap(
+        &self,
+        table: &str,
+        symbol: &str,
+        range: Range<i64>,
+        interval_us: i64,
+        price_col: &str,
+        size_col: &str,
+    ) -> Result<RecordBatch, Error> {
+        if interval_us <= 0 {
+            return Err(Error::InvalidInterval(interval_us));
+        }
+        let table = self.table(table)?;
+        let price_idx = table.f64_column_index(price_col)?;
+        let size_idx = table.f64_column_index(size_col)?;
+
+        // (bucket, sum(price * size), sum(size))
+        let mut buckets: Vec<(i64, f64, f64)> = Vec::new();
+        for (_, part, rows) in table.scan(symbol, range) {
+            let ts = part.timestamps();
+            let price = part.batch.column(price_idx).as_primitive::<Float64Type>();
+            let size = part.batch.column(size_idx).as_primitive::<Float64Type>();
+            for i in rows {
+                if price.is_null(i) || size.is_null(i) {
+                    continue;
+                }
+                let bucket = ts[i].div_euclid(interval_us) * interval_us;
+                let (p, q) = (price.value(i), size.value(i));
+                match buckets.last_mut() {
+                    Some(b) if b.0 == bucket => {
+                        b.1 += p * q;
+                        b.2 += q;
+                    }
+                    _ => buckets.push((bucket, p * q, q)),
+                }
+            }
+        }
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(TIMESTAMP_COL, DataType::Int64, false),
+            Field::new("vwap", DataType::Float64, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(buckets.iter().map(|b| b.0))),
+                Arc::new(Float64Array::from_iter(
+                    buckets.iter().map(|b| (b.2 != 0.0).then(|| b.1 / b.2)),
+                )),
+            ],
+        )?)
+    }
+
+    /// Computes the time-weighted average of `price_col` per `interval_us`
+    /// bucket over `symbol`'s rows in `range`.
+    ///
+    /// Each price is held from its timestamp until the next row, and the last
+    /// price until the end of its bucket (or `range.end`, if earlier). The price
+    /// in force when a bucket starts is carried in from the previous row, so only
+    /// the very first bucket is weighted from its first row rather than its start.
+    /// Returns `timestamp` (bucket start) and `twap` columns; buckets without rows
+    /// are omitted. Rows with a null price are skipped. The column must be Float64.
+    pub fn twap(
+        &self,
+        table: &str,
+        symbol: &str,
+        range: Range<i64>,
+        interval_us: i64,
+        price_col: &str,
+    ) -> Result<RecordBatch, Error> {
+        if interval_us <= 0 {
+            return Err(Error::InvalidInterval(interval_us));
+        }
+        let end = range.end;
+        let table = self.table(table)?;
+        let price_idx = table.f64_column_index(price_col)?;
+
+        // (bucket, integral of price over time, covered duration)
+        let mut buckets: Vec<(i64, f64, i64)> = Vec::new();
+        let mut prev: Option<(i64, f64)> = None;
+        for (_, part, rows) in table.scan(symbol, range) {
+            let ts = part.timestamps();
+            let price = part.batch.column(price_idx).as_primitive::<Float64Type>();
+            for i in rows {
+                if price.is_null(i) {
+                    continue;
+                }
+                let (t, p) = (ts[i], price.value(i));
+                let bucket = t.div_euclid(interval_us) * interval_us;
+                if let Some((pt, pp)) = prev {
+                    let cur = buckets.last_mut().unwrap();
+                    let seg_end = t.min(cur.0 + interval_us);
+                    cur.1 += pp * (seg_end - pt) as f64;
+                    cur.2 += seg_end - pt;
+                    if cur.0 != bucket {
+                        buckets.push((bucket, pp * (t - bucket) as f64, t - bucket));
+                    }
+                } else {
+                    buckets.push((bucket, 0.0, 0));
+                }
+                prev = Some((t, p));
+            }
+        }
+        if let Some((pt, pp)) = prev {
+            let cur = buckets.last_mut().unwrap();
+            let seg_end = end.min(cur.0 + interval_us);
+            cur.1 += pp * (seg_end - pt) as f64;
+            cur.2 += seg_end - pt;
+        }
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(TIMESTAMP_COL, DataType::Int64, false),
+            Field::new("twap", DataType::Float64, false),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(buckets.iter().map(|b| b.0))),
+                Arc::new(Float64Array::from_iter_values(
+                    buckets.iter().map(|b| b.1 / b.2 as f64),
+                )),
+            ],
+        )?)
+    }
+
+    /// Groups every symbol's rows in `range` by (symbol, `interval_us` bucket)
+    /// and computes `aggs` per group.
+    ///
+    /// The result has `symbol` (Utf8) and `timestamp` (bucket start) columns
+    /// followed by one column per aggregation, sorted by symbol then bucket.
+    /// Buckets without rows are omitted.
+    pub fn aggregate(
+        &self,
+        table: &str,
+        range: Range<i64>,
+        interval_us: i64,
+        aggs: &[Aggregation],
+    ) -> Result<RecordBatch, Error> {
+        if interval_us <= 0 {
+            return Err(Error::InvalidInterval(interval_us));
+        }
+        Ok(self.table(table)?.aggregate(range, interval_us, aggs)?)
+    }
+
+    /// For each query timestamp `t`, computes `aggs` over `symbol`'s rows in the
+    /// trailing window `[t - window_us, t]` — the aggregates of
+    /// [`Db::join_window`] without materializing its rows.
+    ///
+    /// The result has the probe `timestamp` column followed by one column per
+    /// aggregation, in probe order.
+    pub fn rolling(
+        &self,
+        table: &str,
+        symbol: &str,
+        timestamps: &RecordBatch,
+        window_us: i64,
+        aggs: &[Aggregation],
+    ) -> Result<RecordBatch, Error> {
+        if window_us < 0 {
+            return Err(Error::InvalidInterval(window_us));
+        }
+        Ok(self
+            .table(table)?
+            .rolling(symbol, timestamps, window_us, aggs)?)
+    }
+
+    /// Materializes [`Db::aggregate`] over all of `src` into the table `dst`, one
+    /// destination partition per source partition, so coarse queries can hit the
+    /// much smaller derived table.
+    ///
+    /// `interval_us` must evenly divide a day so that no bucket straddles a
+    /// partition boundary; for a table with a [`crate::TIMEZONE_METADATA`]
+    /// timezone, it must also divide the UTC offset of each local midnight.
+    /// Existing `dst` partitions for the same days are replaced, as with
+    /// [`WriteMode::Overwrite`], in one [`Db::ingest_days`] write.
+    pub fn downsample(
+        &self,
+        src: &str,
+        dst: &str,
+        interval_us: i64,
+        aggs: &[Aggregation],
+    ) -> Result<(), Error> {
+        if interval_us <= 0 {
+            return Err(Error::InvalidInterval(interval_us));
+        }
+        let table = self.table(src)?;
+        // `dst` is partitioned by the same (possibly local) days as `src`.
+        let metadata: HashMap<String, String> = table
+            .schema
+            .metadata()
+            .get_key_value(TIMEZONE_METADATA)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .into_iter()
+            .collect();
+        let mut batches = Vec::with_capacity(table.partitions.len());
+        for &day in table.partitions.keys() {
+            let span = table.day_span(day);
+            if span.start % interval_us != 0 || span.end % interval_us != 0 {
+                return Err(Error::InvalidInterval(interval_us));
+            }
+            let batch = table.aggregate(span, interval_us, aggs)?;
+            if batch.num_rows() > 0 {
+                let batch = run_end_encode_symbols(batch)?;
+                let schema = Arc::new(
+                    batch
+                        .schema()
+                        .as_ref()
+                        .clone()
+                        .with_metadata(metadata.clone()),
+                );
+                batches.push((day, batch.with_schema(schema)?));
+            }
+        }
+        self.ingest_days(dst, batches, WriteMode::Overwrite)?;
+        Ok(())
+    }
+}
+
+impl Table {
+    /// Resolves `name` to a column index, checking that it is Float64.
+    fn f64_column_index(&self, name: &str) -> Result<usize, ArrowError> {
+        let idx = self.schema.index_of(name)?;
+        if self.schema.field(idx).data_type() != &DataType::Float64 {
+            return Err(ArrowError::SchemaError(format!(
+                "column {name:?} must be Float64"
+            )));
+        }
+        Ok(idx)
+    }
+
+    fn aggregate(
+        &self,
+        range: Range<i64>,
+        interval_us: i64,
+        aggs: &[Aggregation],
+    ) -> Result<RecordBatch, ArrowError> {
+        let groups = self.bucket_groups(range, interval_us);
+
+        let mut fields = vec![
+            Field::new(SYMBOL_COL, DataType::Utf8, false),
+            Field::new(TIMESTAMP_COL, DataType::Int64, false),
+        ];
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                groups.iter().map(|g| g.symbol),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                groups.iter().map(|g| g.bucket),
+            )),
+        ];
+        self.push_aggregates(&groups, aggs, &mut fields, &mut columns)?;
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+
+    fn rolling(
+        &self,
+        symbol: &str,
+        query_ts: &RecordBatch,
+        window_us: i64,
+        aggs: &[Aggregation],
+    ) -> Result<RecordBatch, ArrowError> {
+        let ts_col = timestamps(query_ts)?;
+        let groups: Vec<Group> = ts_col
+            .iter()
+            .map(|&qt| Group {
+                symbol,
+                bucket: qt,
+                segments: self
+                    .scan(symbol, qt.saturating_sub(window_us)..qt.saturating_add(1))
+                    .map(|(day, _, rows)| (day, rows))
+                    .collect(),
+            })
+            .collect();
+
+        let mut fields = vec![Field::new(TIMESTAMP_COL, DataType::Int64, false)];
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(ts_col.to_vec()))];
+        self.push_aggregates(&groups, aggs, &mut fields, &mut columns)?;
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+
+    /// Computes `aggs` over `groups`, appending a field and column for each.
+    fn push_aggregates(
+        &self,
+        groups: &[Group],
+        aggs: &[Aggregation],
+        fields: &mut Vec<Field>,
+        columns: &mut Vec<ArrayRef>,
+    ) -> Result<(), ArrowError> {
+        for agg in aggs {
+            let column = self.aggregate_column(groups, agg)?;
+            let nullable = agg.func != AggFn::Count;
+            fields.push(Field::new(
+                agg.output_name(),
+                column.data_type().clone(),
+                nullable,
+            ));
+            columns.push(column);
+        }
+        Ok(())
+    }
+
+    fn bucket_groups(&self, range: Range<i64>, interval_us: i64) -> Vec<Group<'_>> {
+        let symbols: BTreeSet<&str> = self
+            .partitions_in(range.clone())
+            .flat_map(|(_, part)| part.symbol_index.keys().map(String::as_str))
+            .collect();
+
+        let mut groups: Vec<Group> = Vec::new();
+        for symbol in symbols {
+            for (day, part, rows) in self.scan(symbol, range.clone()) {
+                let ts = part.timestamps();
+                let mut lo = rows.start;
+                while lo < rows.end {
+                    let bucket = ts[lo].div_euclid(interval_us) * interval_us;
+                    let bucket_end = bucket.saturating_add(interval_us);
+                    let hi = lo + ts[lo..rows.end].partition_point(|&t| t < bucket_end);
+                    match groups.last_mut() {
+                        Some(g) if g.symbol == symbol && g.bucket == bucket => {
+                            g.segments.push((day, lo..hi));
+                        }
+                        _ => groups.push(Group {
+                            symbol,
+                            bucket,
+                            segments: vec![(day, lo..hi)],
+                        }),
+                    }
+                    lo = hi;
+                }
+            }
+        }
+        groups
+    }
+
+    fn aggregate_column(
+        &self,
+        groups: &[Group],
+        agg: &Aggregation,
+    ) -> Result<ArrayRef, ArrowError> {
+        if key_columns(&self.schema).contains(&agg.column) {
+            return Err(ArrowError::SchemaError(format!(
+                "cannot aggregate key column {:?}",
+                agg.column
+            )));
+        }
+        let col_idx = self.schema.index_of(&agg.column)?;
+        let field = self.schema.field(col_idx);
+        let column = |day: &EpochDay| self.partitions[day].batch.column(col_idx);
+
+        match agg.func {
+            AggFn::Count => Ok(Arc::new(Int64Array::from_iter_values(groups.iter().map(
+                |g| {
+                    g.segments
+                        .iter()
+                        .map(|(d, r)| {
+                            (r.len() - column(d).slice(r.start, r.len()).null_count()) as i64
+                        })
+                        .sum::<i64>()
+                },
+            )))),
+            AggFn::First | AggFn::Last => {
+                let rows: Vec<Option<(EpochDay, usize)>> = groups
+                    .iter()
+                    .map(|g| {
+                        let mut valid = g.segments.iter().flat_map(|(d, r)| {
+                            let col = column(d);
+                            r.clone().filter(|&i| col.is_valid(i)).map(|i| (*d, i))
+                        });
+                        if agg.func == AggFn::First {
+                            valid.next()
+                        } else {
+                            valid.last()
+                        }
+                    })
+                    .collect();
+                let schema = Arc::new(Schema::new(vec![field.clone().with_nullable(true)]));
+                Ok(self.gather(&schema, &rows)?.remove(0))
+            }
+            AggFn::Min | AggFn::Max | AggFn::Sum | AggFn::Mean => match field.data_type() {
+                DataType::Int64 => {
+                    fold_numeric::<Int64Type>(self, col_idx, groups, agg.func, |v| v as f64)
+                }
+                DataType::Float64 => {
+                    fold_numeric::<Float64Type>(self, col_idx, groups, agg.func, |v| v)
+                }
+                other => Err(ArrowError::SchemaError(format!(
+                    "cannot compute {} of column {:?} with type {other}",
+                    agg.func.name(),
+                    agg.column,
+                ))),
+            },
+        }
+    }
+}
+
+/// Replaces a sorted Utf8 `symbol` column with the run-end encoded form that
+/// table partitions store.
+fn run_end_encode_symbols(batch: RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let symbols = batch.column(0).as_string::<i32>();
+    let run_array = encode_symbols(symbols.iter().map(Option::unwrap))?;
+
+    let schema = batch.schema();
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    fields[0] = Field::new(SYMBOL_COL, run_array.data_type().clone(), false);
+    let mut columns = batch.columns().to_vec();
+    columns[0] = run_array;
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+}
+
+/// Computes a numeric `func` per group over a primitive column.
+fn fold_numeric<T: ArrowNumericType>(
+    table: &Table,
+    col_idx: usize,
+    groups: &[Group],
+    func: AggFn,
+    to_f64: fn(T::Native) -> f64,
+) -> Result<ArrayRef, ArrowError> {
+    if func == AggFn::Mean {
+        return Ok(Arc::new(Float64Array::from_iter(groups.iter().map(|g| {
+            let (mut sum, mut n) = (0.0, 0usize);
+            for slice in group_slices::<T>(table, col_idx, g) {
+                for v in slice.iter().flatten() {
+                    sum += to_f64(v);
+                    n += 1;
+                }
+            }
+            (n > 0).then(|| sum / n as f64)
+        }))));
+    }
+    // Sums are checked, so that one too large for the type is an error
+    // rather than a wrapped-around value.
+    let fold = |g: &Group| -> Result<Option<T::Native>, ArrowError> {
+        let mut acc = None;
+        for slice in group_slices::<T>(table, col_idx, g) {
+            let value = match func {
+                AggFn::Min => arrow::compute::min(&slice),
+                AggFn::Max => arrow::compute::max(&slice),
+                _ => arrow::compute::sum_checked(&slice)?,
+            };
+            acc = match (acc, value) {
+                (Some(a), Some(b)) => Some(match func {
+                    AggFn::Min if b.is_lt(a) => b,
+                    AggFn::Max if b.is_gt(a) => b,
+                    AggFn::Min | AggFn::Max => a,
+                    _ => a.add_checked(b)?,
+                }),
+                (a, b) => a.or(b),
+            };
+        }
+        Ok(acc)
+    };
+    let values = groups.iter().map(fold).collect::<Result<Vec<_>, _>>()?;
+    Ok(Arc::new(PrimitiveArray::<T>::from_iter(values)))
+}
+
+fn group_slices<'a, T: ArrowNumericType>(
+    table: &'a Table,
+    col_idx: usize,
+    group: &'a Group,
+) -> impl Iterator<Item = PrimitiveArray<T>> + 'a {
+    group.segments.iter().map(move |(d, r)| {
+        table.partitions[d]
+            .batch
+            .column(col_idx)
+            .as_primitive::<T>()
+            .slice(r.start, r.len())
+    })
+}
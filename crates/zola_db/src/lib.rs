@@ -1,16 +1,19 @@
-use std::collections::{BTreeMap, HashMap};
-use std::fs::{self, File};
-use std::ops::Range;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
+use std::fs::{self, File, TryLockError};
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::{Bound, Range, RangeBounds, RangeInclusive};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
 
-use arrow::array::types::{Int32Type, Int64Type};
-use arrow::array::{ArrayRef, AsArray, RunArray, StringArray, new_null_array};
+use arrow::array::types::{ArrowPrimitiveType, Float64Type, Int32Type, Int64Type};
+use arrow::array::{Array, ArrayRef, AsArray, Float64Array, Int64Array, RunArray, StringArray, new_null_array};
 use arrow::buffer::Buffer;
-use arrow::compute::interleave;
-use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::compute::{concat_batches, interleave};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
 use arrow::ipc::convert::fb_to_schema;
-use arrow::ipc::reader::{FileDecoder, read_footer_length};
+use arrow::ipc::reader::{FileDecoder, FileReader, read_footer_length};
 use arrow::ipc::root_as_footer;
 use arrow::ipc::writer::FileWriter;
 use arrow::record_batch::RecordBatch;
@@ -21,12 +24,39 @@ pub enum Error {
     #[error("table not found: {0}")]
     TableNotFound(String),
 
+    #[error("table already exists: {0}")]
+    TableExists(String),
+
+    #[error("invalid table name: {0:?}")]
+    InvalidTableName(String),
+
     #[error("symbol {0:?} appears in multiple non-contiguous runs")]
     NonContiguousSymbol(String),
 
     #[error("unsorted timestamps for symbol {0:?}")]
     UnsortedTimestamps(String),
 
+    #[error("column {0:?} must not contain nulls")]
+    NullValues(String),
+
+    #[error("invalid interval: {0}")]
+    InvalidInterval(i64),
+
+    #[error("partition file {} is truncated or not an Arrow file", .0.display())]
+    InvalidFile(PathBuf),
+
+    #[error("write would grow table {table:?} to {bytes} bytes, over its quota of {limit}")]
+    QuotaExceeded { table: String, bytes: u64, limit: u64 },
+
+    #[error("database is open read-only")]
+    ReadOnly,
+
+    #[error("table {0:?} already has a partition for {date}", date = jiff::civil::Date::from(*.1))]
+    PartitionExists(String, EpochDay),
+
+    #[error("partition of table {0:?} for {date} changed", date = jiff::civil::Date::from(*.1))]
+    PartitionChanged(String, EpochDay),
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 
@@ -34,20 +64,164 @@ pub enum Error {
     Arrow(#[from] arrow::error::ArrowError),
 }
 
-pub use zola_db_core::{Direction, EpochDay, SYMBOL_COL, TIMESTAMP_COL};
+pub use zola_db_core::{Direction, Durability, EpochDay, MICROS_PER_DAY, SYMBOL_COL, TIMESTAMP_COL, WriteMode};
+
+mod agg;
+mod row;
+mod watch;
+
+/// Options for the as-of joins [`Db::join_asof_with`] and [`Db::join_asof_keyed`].
+#[derive(Debug, Clone)]
+pub struct AsofOptions {
+    pub direction: Direction,
+    /// When a probe has no match, reuse the previous probe's match for the same
+    /// key, like pandas `ffill`. Probes are taken in the order given, so this is
+    /// intended for time-ordered grids.
+    pub fill_forward: bool,
+    /// Skip candidate rows that don't satisfy this predicate, continuing the
+    /// search further back (or forward) for one that does.
+    pub filter: Option<Predicate>,
+    /// How many of the table's partitions before (or after) the probe's day a
+    /// match may come from, if none is found on the day itself. `None` is
+    /// unlimited; `Some(0)` never bridges calendar days, so each probe only sees
+    /// rows from its own day.
+    pub lookback: Option<usize>,
+    /// The farthest day a match may come from — the earliest for backward
+    /// joins, the latest for forward ones — however far `lookback` allows.
+    /// Useful with unlimited lookback on reference-data tables, where the last
+    /// known value may be weeks old but shouldn't be arbitrarily stale.
+    pub day_limit: Option<EpochDay>,
+    /// Never match a row with an earlier timestamp than this, e.g. the start of
+    /// the trading session, however far `lookback` allows.
+    pub min_timestamp: Option<i64>,
+    /// Linearly interpolate Float64 columns at each probe's timestamp between
+    /// its backward and forward matches, rather than copying the `direction`
+    /// match; other columns still come from that match. The interpolated
+    /// columns are null unless the probe has both matches.
+    pub interpolate: bool,
+}
+
+impl AsofOptions {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            fill_forward: false,
+            filter: None,
+            lookback: None,
+            day_limit: None,
+            min_timestamp: None,
+            interpolate: false,
+        }
+    }
+
+    /// When interpolating, the options for finding each probe's other neighbor.
+    fn opposite(&self) -> Option<AsofOptions> {
+        if !self.interpolate {
+            return None;
+        }
+        let direction = match self.direction {
+            Direction::Backward => Direction::Forward,
+            Direction::Forward => Direction::Backward,
+        };
+        // `day_limit` bounds the search in the `direction` only.
+        Some(AsofOptions {
+            direction,
+            fill_forward: false,
+            day_limit: None,
+            ..self.clone()
+        })
+    }
+
+    /// Whether probes on `day` are past `day_limit`, and so can't match.
+    fn beyond_day_limit(&self, day: EpochDay) -> bool {
+        match (self.direction, self.day_limit) {
+            (Direction::Backward, Some(limit)) => day < limit,
+            (Direction::Forward, Some(limit)) => day > limit,
+            (_, None) => false,
+        }
+    }
+}
+
+/// Counters from an as-of join, returned by [`Db::join_asof_explain`] to help
+/// diagnose slow probe batches.
+#[derive(Debug, Clone, Default)]
+pub struct AsofStats {
+    pub probes: usize,
+    /// Partitions holding rows for the symbol.
+    pub partitions: usize,
+    /// Candidate rows considered, including those rejected by the filter.
+    pub rows_examined: usize,
+    /// Probes with a match, including forward-filled ones.
+    pub matched: usize,
+    /// Time spent locating each probe's row.
+    pub locate: Duration,
+    /// Time spent copying matched rows into the output columns.
+    pub gather: Duration,
+}
+
+/// A condition on a value column, used to filter as-of candidates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    /// The column is not null.
+    NotNull(String),
+    /// The column is not null and compares to the value as given. The column
+    /// must be Int64 or Float64; Int64 values are compared as f64.
+    Compare(String, CmpOp, f64),
+}
+
+/// Which rows [`Db::sample`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stride {
+    /// Every nth row, starting with the first.
+    Rows(usize),
+    /// The first row in each bucket of this many microseconds, aligned to
+    /// multiples of it like [`Db::aggregate`]'s buckets.
+    Interval(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+pub use agg::{AggFn, Aggregation};
+#[doc(hidden)]
+pub use row::__private;
+pub use row::{DecodeRows, RowValue, RowView, ZolaRow};
+pub use watch::Watcher;
+pub use zola_db_derive::ZolaRow;
 
 struct Partition {
     symbol_index: HashMap<String, Range<usize>>,
     batch: RecordBatch,
+    /// Size of the partition file; 0 until written.
+    bytes: u64,
+    /// The contents of the file the partition was loaded from, which `batch`
+    /// points into.
+    file: Option<Buffer>,
+    /// See [`Partition::checksum`].
+    checksum: OnceLock<u32>,
 }
 
 impl Partition {
     /// Builds the symbol index and validates timestamp sortedness per symbol.
+    /// Key and timestamp columns must not contain nulls, which joins can't
+    /// tell apart from a probe without a match.
     fn new(batch: RecordBatch) -> Result<Self, Error> {
         let symbol_index = build_symbol_index(&batch)?;
         let ts_col = batch.column_by_name(TIMESTAMP_COL).ok_or_else(|| {
             arrow::error::ArrowError::SchemaError("missing timestamp column".into())
         })?;
+        for name in key_columns(&batch.schema()).iter().map(String::as_str).chain([TIMESTAMP_COL]) {
+            if batch.column_by_name(name).is_some_and(|c| c.logical_null_count() > 0) {
+                return Err(Error::NullValues(name.to_string()));
+            }
+        }
         let ts = ts_col
             .as_any()
             .downcast_ref::<arrow::array::PrimitiveArray<Int64Type>>()
@@ -63,18 +237,34 @@ impl Partition {
         Ok(Self {
             symbol_index,
             batch,
+            bytes: 0,
+            file: None,
+            checksum: OnceLock::new(),
         })
     }
 
     /// Reads a single-batch Arrow IPC file and wraps it as a `Partition`.
-    fn load(path: &Path) -> Result<Self, Error> {
+    fn load(path: &Path, mmap: bool) -> Result<Self, Error> {
         let file = File::open(path)?;
-        let mmap = unsafe { memmap2::Mmap::map(&file)? };
-        let bytes = bytes::Bytes::from_owner(mmap);
+        let checksum = saved_checksum(path, &file.metadata()?).map_or_else(OnceLock::new, OnceLock::from);
+        let bytes = if mmap {
+            bytes::Bytes::from_owner(unsafe { memmap2::Mmap::map(&file)? })
+        } else {
+            let mut bytes = Vec::new();
+            (&file).read_to_end(&mut bytes)?;
+            bytes::Bytes::from(bytes)
+        };
         let buffer = Buffer::from(bytes);
 
-        let trailer_start = buffer.len() - 10;
-        let footer_len = read_footer_length(buffer[trailer_start..].try_into().unwrap())?;
+        // A file cut short by a crash mid-write loses the trailing footer
+        // length and magic, which the IPC format puts last.
+        let torn = || Error::InvalidFile(path.to_path_buf());
+        let trailer_start = buffer.len().checked_sub(10).ok_or_else(torn)?;
+        let footer_len = read_footer_length(buffer[trailer_start..].try_into().unwrap())
+            .map_err(|_| torn())?;
+        if footer_len > trailer_start {
+            return Err(torn());
+        }
         let footer = root_as_footer(&buffer[trailer_start - footer_len..trailer_start])
             .map_err(|e| arrow::error::ArrowError::IpcError(e.to_string()))?;
 
@@ -114,265 +304,2120 @@ impl Partition {
         Ok(Self {
             symbol_index,
             batch,
+            bytes: buffer.len() as u64,
+            file: Some(buffer),
+            checksum,
         })
     }
 
+    /// Returns the rows for `symbol` with timestamps in `range`, if any.
+    fn rows_in(&self, symbol: &str, range: &Range<i64>) -> Option<Range<usize>> {
+        let rows = self.symbol_index.get(symbol)?;
+        let ts = &self.timestamps()[rows.clone()];
+        let lo = ts.partition_point(|&t| t < range.start);
+        let hi = ts.partition_point(|&t| t < range.end);
+        (lo < hi).then(|| rows.start + lo..rows.start + hi)
+    }
 
-    /// Writes this partition's batch to an Arrow IPC file, creating parent dirs.
-    /// Uses write-to-temp + rename for atomicity and mmap safety.
-    fn save(&self, path: &Path) -> Result<(), Error> {
-        let parent = path.parent().expect("partition path must have a parent");
-        fs::create_dir_all(parent)?;
+    fn timestamps(&self) -> &[i64] {
+        self.batch
+            .column_by_name(TIMESTAMP_COL)
+            .unwrap()
+            .as_primitive::<Int64Type>()
+            .values()
+    }
 
-        let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
-        let mut writer = FileWriter::try_new(tmp.as_file_mut(), &self.batch.schema())?;
+    /// Writes the partition to `w` as a single-batch Arrow IPC file.
+    fn write(&self, w: impl std::io::Write) -> Result<(), Error> {
+        let mut writer = FileWriter::try_new(w, &self.batch.schema())?;
         writer.write(&self.batch)?;
         writer.finish()?;
-        tmp.persist(path).map_err(|e| e.error)?;
         Ok(())
     }
+
+    /// The CRC-32 of the partition's file — for an in-memory `Db`, of the
+    /// file [`Partition::write`] would make — so equal for a copy of the file.
+    /// Known from writing the file, or from the record [`Partition::stage`]
+    /// left beside it, otherwise computed on first use.
+    fn checksum(&self) -> Result<u32, Error> {
+        if let Some(&checksum) = self.checksum.get() {
+            return Ok(checksum);
+        }
+        let checksum = match &self.file {
+            Some(file) => crc32fast::hash(file.as_slice()),
+            None => {
+                let mut crc = CrcWriter::new(std::io::sink());
+                self.write(&mut crc)?;
+                crc.crc.finalize()
+            }
+        };
+        Ok(*self.checksum.get_or_init(|| checksum))
+    }
+
+    /// Writes the partition to a temporary file beside `path`, for [`commit`]
+    /// to move into place, and records its size and checksum.
+    fn stage(&mut self, path: &Path, durability: Durability) -> Result<tempfile::TempPath, Error> {
+        fs::create_dir_all(path.parent().expect("partition path must have a parent"))?;
+        let mut tmp = staging_file(path)?;
+        let mut crc = CrcWriter::new(tmp.as_file_mut());
+        self.write(&mut crc)?;
+        let checksum = crc.crc.finalize();
+        self.checksum = OnceLock::from(checksum);
+        if durability == Durability::Always {
+            tmp.as_file().sync_all()?;
+        }
+        let metadata = tmp.as_file().metadata()?;
+        self.bytes = metadata.len();
+        save_checksum(path, &metadata, checksum)?;
+        Ok(tmp.into_temp_path())
+    }
+
+    /// Merges `other`'s rows into this partition's. Each key's rows stay in
+    /// timestamp order, with this partition's rows first among equal
+    /// timestamps; keys new to this partition follow the existing ones.
+    fn append(&self, other: &Partition) -> Result<Partition, Error> {
+        // Each key's rows in this partition and in `other`.
+        let mut keys: Vec<(Option<&Range<usize>>, _)> = self
+            .symbol_index
+            .iter()
+            .map(|(key, range)| (Some(range), other.symbol_index.get(key)))
+            .collect();
+        let mut added: Vec<_> = other
+            .symbol_index
+            .iter()
+            .filter(|(key, _)| !self.symbol_index.contains_key(*key))
+            .map(|(_, range)| (None, Some(range)))
+            .collect();
+        keys.sort_by_key(|(range, _)| range.map(|r| r.start));
+        added.sort_by_key(|(_, range)| range.map(|r| r.start));
+        keys.extend(added);
+
+        let (ts, other_ts) = (self.timestamps(), other.timestamps());
+        let mut indices: Vec<(usize, usize)> = Vec::with_capacity(ts.len() + other_ts.len());
+        // For each key, its first row in the merged batch and the source of its
+        // key values.
+        let mut runs: Vec<(usize, usize, usize)> = Vec::with_capacity(keys.len());
+        for (mine, theirs) in keys {
+            let (source, first) = match mine {
+                Some(r) => (0, r.start),
+                None => (1, theirs.unwrap().start),
+            };
+            runs.push((indices.len(), source, first));
+            let mut mine = mine.cloned().unwrap_or_default().peekable();
+            let mut theirs = theirs.cloned().unwrap_or_default().peekable();
+            loop {
+                let next = match (mine.peek(), theirs.peek()) {
+                    (Some(&i), Some(&j)) if other_ts[j] < ts[i] => (1, theirs.next().unwrap()),
+                    (Some(_), _) => (0, mine.next().unwrap()),
+                    (None, Some(_)) => (1, theirs.next().unwrap()),
+                    (None, None) => break,
+                };
+                indices.push(next);
+            }
+        }
+
+        let schema = self.batch.schema();
+        let keys = key_columns(&schema);
+        let sources = [&self.batch, &other.batch];
+        let columns = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(c, field)| {
+                if !keys.contains(field.name()) {
+                    let arrays = [sources[0].column(c).as_ref(), sources[1].column(c).as_ref()];
+                    return Ok(interleave(&arrays, &indices)?);
+                }
+                let values = [key_runs(sources[0], field.name())?, key_runs(sources[1], field.name())?];
+                let mut column: Vec<&str> = Vec::with_capacity(indices.len());
+                for (k, &(start, source, row)) in runs.iter().enumerate() {
+                    let end = runs.get(k + 1).map_or(indices.len(), |&(next, _, _)| next);
+                    let (ends, strings) = values[source];
+                    let value = strings.value(ends.partition_point(|&e| e as usize <= row));
+                    column.extend(std::iter::repeat_n(value, end - start));
+                }
+                Ok(row::encode_symbols(column.into_iter())?)
+            })
+            .collect::<Result<Vec<ArrayRef>, Error>>()?;
+        Partition::new(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+/// Schema metadata key declaring extra key columns beyond `symbol`, as a
+/// comma-separated list (e.g. `"venue"`).
+///
+/// Like `symbol`, each extra key column must be RunEndEncoded(Int32, Utf8), and
+/// rows must be grouped by the full key with timestamps sorted within each group.
+/// The partition index is then built over the composite key, which APIs taking
+/// or returning a single `symbol` string represent as the key values joined by
+/// [`KEY_SEPARATOR`].
+pub const KEYS_METADATA: &str = "zola_db.keys";
+
+/// Schema metadata key declaring the IANA timezone whose local dates the
+/// table's partitions are cut by (e.g. `"America/New_York"`), rather than UTC
+/// dates. Probes are then resolved to partitions by their local date.
+pub const TIMEZONE_METADATA: &str = "zola_db.timezone";
+
+/// Separator between key values in a composite key string.
+pub const KEY_SEPARATOR: &str = "\u{1f}";
+
+/// Returns the table's key columns: `symbol` followed by any declared in
+/// [`KEYS_METADATA`].
+pub fn key_columns(schema: &Schema) -> Vec<String> {
+    let mut keys = vec![SYMBOL_COL.to_string()];
+    if let Some(extra) = schema.metadata().get(KEYS_METADATA) {
+        keys.extend(extra.split(',').map(str::trim).filter(|k| !k.is_empty()).map(String::from));
+    }
+    keys
 }
 
 fn build_symbol_index(batch: &RecordBatch) -> Result<HashMap<String, Range<usize>>, Error> {
-    let col = batch.column_by_name(SYMBOL_COL).ok_or_else(|| {
-        arrow::error::ArrowError::SchemaError("missing symbol column".into())
+    let runs = key_columns(&batch.schema())
+        .iter()
+        .map(|name| key_runs(batch, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Composite key runs end wherever any key column's run ends.
+    let mut run_ends: Vec<i32> = runs.iter().flat_map(|(ends, _)| ends.iter().copied()).collect();
+    run_ends.sort_unstable();
+    run_ends.dedup();
+
+    let mut index = HashMap::with_capacity(run_ends.len());
+    let mut start = 0usize;
+    for end in run_ends {
+        let end = end as usize;
+        let mut key = String::new();
+        for (i, (ends, values)) in runs.iter().enumerate() {
+            if i > 0 {
+                key.push_str(KEY_SEPARATOR);
+            }
+            key.push_str(values.value(ends.partition_point(|&e| e as usize <= start)));
+        }
+        if index.contains_key(&key) {
+            return Err(Error::NonContiguousSymbol(key));
+        }
+        index.insert(key, start..end);
+        start = end;
+    }
+    Ok(index)
+}
+
+/// Returns the timestamps of `batch`, a caller's query or probes, which
+/// must have an Int64 timestamp column.
+fn timestamps(batch: &RecordBatch) -> Result<&[i64], arrow::error::ArrowError> {
+    let col = batch.column_by_name(TIMESTAMP_COL).ok_or_else(|| {
+        arrow::error::ArrowError::SchemaError("missing timestamp column".into())
+    })?;
+    let ts = col.as_primitive_opt::<Int64Type>().ok_or_else(|| {
+        arrow::error::ArrowError::SchemaError("timestamp column must be Int64".into())
+    })?;
+    Ok(ts.values())
+}
+
+/// Returns the run ends and run values of the key column `name`.
+fn key_runs<'a>(batch: &'a RecordBatch, name: &str) -> Result<(&'a [i32], &'a StringArray), Error> {
+    let col = batch.column_by_name(name).ok_or_else(|| {
+        arrow::error::ArrowError::SchemaError(format!("missing key column {name:?}"))
     })?;
     let run_array = col
         .as_any()
         .downcast_ref::<RunArray<Int32Type>>()
         .ok_or_else(|| {
-            arrow::error::ArrowError::SchemaError(
-                "symbol column must be RunEndEncoded(Int32, Utf8)".into(),
-            )
+            arrow::error::ArrowError::SchemaError(format!(
+                "key column {name:?} must be RunEndEncoded(Int32, Utf8)"
+            ))
         })?;
-    let run_ends = run_array.run_ends().values();
     let values = run_array
         .values()
         .as_any()
         .downcast_ref::<StringArray>()
         .ok_or_else(|| {
-            arrow::error::ArrowError::SchemaError("symbol values must be Utf8".into())
+            arrow::error::ArrowError::SchemaError(format!("key column {name:?} values must be Utf8"))
         })?;
+    Ok((run_array.run_ends().values(), values))
+}
 
-    let mut index = HashMap::with_capacity(run_ends.len());
-    let mut start = 0usize;
-    for (i, &end) in run_ends.iter().enumerate() {
-        let end = end as usize;
-        let symbol = values.value(i);
-        if index.insert(symbol.to_string(), start..end).is_some() {
-            return Err(Error::NonContiguousSymbol(symbol.to_string()));
+/// Picks the as-of match for a probe on `day`. `in_day` holds the probe's
+/// position among the day's rows for the key, and `others` the key's rows in
+/// the partitions it may fall back to, in day order. Candidates are considered
+/// nearest first.
+fn pick_match<'a>(
+    day: EpochDay,
+    in_day: Option<(usize, Range<usize>, &'a Partition)>,
+    others: impl DoubleEndedIterator<Item = (EpochDay, Range<usize>, &'a Partition)>,
+    options: &AsofOptions,
+    filter: Option<&RowFilter>,
+    examined: &mut usize,
+) -> Option<(EpochDay, usize)> {
+    match options.direction {
+        Direction::Backward => {
+            let same_day = in_day
+                .into_iter()
+                .flat_map(|(pos, range, part)| (range.start..pos).rev().map(move |i| (day, part, i)));
+            let earlier = others.rev().flat_map(|(d, range, part)| range.rev().map(move |i| (d, part, i)));
+            let candidates = same_day
+                .chain(earlier)
+                .inspect(|_| *examined += 1)
+                .take_while(|&(_, part, i)| options.min_timestamp.is_none_or(|min| part.timestamps()[i] >= min));
+            first_match(candidates, filter)
+        }
+        Direction::Forward => {
+            let same_day = in_day
+                .into_iter()
+                .flat_map(|(pos, range, part)| (pos..range.end).map(move |i| (day, part, i)));
+            let later = others.flat_map(|(d, range, part)| range.map(move |i| (d, part, i)));
+            let candidates = same_day
+                .chain(later)
+                .inspect(|_| *examined += 1)
+                .skip_while(|&(_, part, i)| options.min_timestamp.is_some_and(|min| part.timestamps()[i] < min));
+            first_match(candidates, filter)
         }
-        start = end;
     }
-    Ok(index)
 }
 
+/// Returns the first candidate row passing `filter`, if any.
+fn first_match<'a>(
+    mut candidates: impl Iterator<Item = (EpochDay, &'a Partition, usize)>,
+    filter: Option<&RowFilter>,
+) -> Option<(EpochDay, usize)> {
+    let (day, _, row) = match filter {
+        Some(f) => candidates.find(|&(_, part, row)| f.test(part, row))?,
+        None => candidates.next()?,
+    };
+    Some((day, row))
+}
+
+/// A [`Predicate`] resolved against a table's schema.
+struct RowFilter {
+    col_idx: usize,
+    cmp: Option<(CmpOp, f64)>,
+}
+
+impl RowFilter {
+    fn test(&self, part: &Partition, row: usize) -> bool {
+        let col = part.batch.column(self.col_idx);
+        if col.is_null(row) {
+            return false;
+        }
+        let Some((op, rhs)) = self.cmp else {
+            return true;
+        };
+        let lhs = match col.data_type() {
+            DataType::Int64 => col.as_primitive::<Int64Type>().value(row) as f64,
+            _ => col.as_primitive::<Float64Type>().value(row),
+        };
+        match op {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+/// How `locate_asof` finds a probe's position among a day's timestamps.
+#[derive(Debug, Clone, Copy)]
+enum Search {
+    /// Exponential steps from the previous probe's position, then a binary
+    /// search — near-linear for dense grids, logarithmic for sparse probes.
+    Gallop,
+    /// Step forward one row at a time — a merge walk, best when sorted probes
+    /// are about as dense as the rows.
+    Linear,
+}
+
+impl Search {
+    /// Returns the index of the first element of `ts[from..]` not satisfying
+    /// `pred`, given that all of `ts[..from]` satisfy it.
+    fn partition_point(self, ts: &[i64], from: usize, pred: impl Fn(i64) -> bool) -> usize {
+        match self {
+            Search::Gallop => {
+                let mut lo = from;
+                let mut step = 1;
+                while lo + step <= ts.len() && pred(ts[lo + step - 1]) {
+                    lo += step;
+                    step *= 2;
+                }
+                // std's `partition_point` is already a branchless loop (since Rust
+                // 1.83), and galloping keeps the window small, so a hand-written
+                // or SIMD kernel wouldn't pay for its complexity.
+                let hi = (lo + step).min(ts.len());
+                lo + ts[lo..hi].partition_point(|&t| pred(t))
+            }
+            Search::Linear => {
+                let mut i = from;
+                while i < ts.len() && pred(ts[i]) {
+                    i += 1;
+                }
+                i
+            }
+        }
+    }
+}
+
+/// Shared by reference between [`Db`] and in-flight queries; a write clones
+/// the table, which shares its partitions, and swaps the copy in.
+#[derive(Clone)]
 struct Table {
     schema: SchemaRef,
-    partitions: BTreeMap<EpochDay, Partition>,
+    partitions: BTreeMap<EpochDay, Arc<Partition>>,
+    /// From [`TIMEZONE_METADATA`]; `None` for UTC.
+    timezone: Option<jiff::tz::TimeZone>,
 }
 
 impl Table {
+    fn new(schema: SchemaRef) -> Result<Self, arrow::error::ArrowError> {
+        let timezone = schema
+            .metadata()
+            .get(TIMEZONE_METADATA)
+            .map(|name| {
+                jiff::tz::TimeZone::get(name).map_err(|e| {
+                    arrow::error::ArrowError::SchemaError(format!("invalid timezone {name:?}: {e}"))
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            schema,
+            partitions: BTreeMap::new(),
+            timezone,
+        })
+    }
+
+    /// Checks that a batch with `schema` can be stored in this table.
+    fn check_schema(&self, schema: &SchemaRef) -> Result<(), arrow::error::ArrowError> {
+        if self.schema.fields() != schema.fields()
+            || key_columns(&self.schema) != key_columns(schema)
+            || self.schema.metadata().get(TIMEZONE_METADATA) != schema.metadata().get(TIMEZONE_METADATA)
+        {
+            return Err(arrow::error::ArrowError::SchemaError(format!(
+                "expected schema {:?}, got {:?}",
+                self.schema.fields(),
+                schema.fields(),
+            )));
+        }
+        Ok(())
+    }
+
+    /// The partition for `day` of this table, named `name`.
+    fn partition(&self, name: &str, day: EpochDay) -> Result<&Arc<Partition>, Error> {
+        self.partitions.get(&day).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("table {name:?} has no partition for {}", jiff::civil::Date::from(day)),
+            )
+            .into()
+        })
+    }
+
+    /// Returns the partition day holding timestamp `ts`.
+    fn day_of(&self, ts: i64) -> EpochDay {
+        match &self.timezone {
+            Some(tz) => EpochDay::from_timestamp_us_in(ts, tz),
+            None => EpochDay::from_timestamp_us(ts),
+        }
+    }
+
+    /// Returns the timestamps covered by the partition for `day`.
+    fn day_span(&self, day: EpochDay) -> Range<i64> {
+        let next = EpochDay(day.0 + 1);
+        match &self.timezone {
+            Some(tz) => day.start_timestamp_us_in(tz)..next.start_timestamp_us_in(tz),
+            None => day.start_timestamp_us()..next.start_timestamp_us(),
+        }
+    }
+
     /// For each query timestamp, finds the matching row for `symbol` using an
     /// as-of join in the given `direction`.
     fn join_asof(
         &self,
         symbol: &str,
         query_ts: &RecordBatch,
-        direction: Direction,
+        options: &AsofOptions,
+        stats: &mut AsofStats,
     ) -> Result<RecordBatch, arrow::error::ArrowError> {
-        let ts_col = query_ts.column_by_name(TIMESTAMP_COL).unwrap().as_primitive::<Int64Type>().values();
+        let ts_col = timestamps(query_ts)?;
         let out_schema = output_schema(&self.schema);
+        let start = Instant::now();
+        let rows = self.locate_asof(symbol, ts_col, options, Search::Gallop, stats)?;
+        let opposite = self.locate_opposite(symbol, ts_col, options, Search::Gallop)?;
+        stats.locate += start.elapsed();
+        let start = Instant::now();
+        let columns = self.asof_columns(&out_schema, ts_col, &rows, &opposite, options)?;
+        stats.gather += start.elapsed();
+        RecordBatch::try_new(out_schema, columns)
+    }
 
-        // Pre-resolve symbol ranges once across all partitions.
-        let resolved: BTreeMap<EpochDay, (Range<usize>, &[i64])> = self
-            .partitions
+    /// Like `join_asof` for a single primitive column, writing into `out`.
+    fn join_asof_into<T: ArrowPrimitiveType>(
+        &self,
+        symbol: &str,
+        query_ts: &[i64],
+        column: &str,
+        options: &AsofOptions,
+        out: &mut [Option<T::Native>],
+    ) -> Result<(), arrow::error::ArrowError> {
+        if out.len() != query_ts.len() {
+            return Err(arrow::error::ArrowError::InvalidArgumentError(format!(
+                "output buffer holds {} values, expected {}",
+                out.len(),
+                query_ts.len()
+            )));
+        }
+        let col_idx = self.schema.index_of(column)?;
+        let data_type = self.schema.field(col_idx).data_type();
+        if data_type != &T::DATA_TYPE {
+            return Err(arrow::error::ArrowError::SchemaError(format!(
+                "column {column:?} has type {data_type}, not {}",
+                T::DATA_TYPE
+            )));
+        }
+        let rows = self.locate_asof(symbol, query_ts, options, Search::Gallop, &mut AsofStats::default())?;
+        for (slot, row) in out.iter_mut().zip(rows) {
+            *slot = row.and_then(|(day, i)| {
+                let col = self.partitions[&day].batch.column(col_idx);
+                col.is_valid(i).then(|| col.as_primitive::<T>().value(i))
+            });
+        }
+        Ok(())
+    }
+
+    /// Like `join_asof`, but returns only each match's timestamp.
+    fn asof_timestamps(
+        &self,
+        symbol: &str,
+        query_ts: &RecordBatch,
+        options: &AsofOptions,
+    ) -> Result<Int64Array, arrow::error::ArrowError> {
+        let ts_col = timestamps(query_ts)?;
+        let rows = self.locate_asof(symbol, ts_col, options, Search::Gallop, &mut AsofStats::default())?;
+        Ok(rows
             .iter()
-            .filter_map(|(&day, part)| {
-                let range = part.symbol_index.get(symbol)?.clone();
-                let ts = part.batch
-                    .column_by_name(TIMESTAMP_COL)
-                    .unwrap()
-                    .as_primitive::<Int64Type>()
-                    .values()
-                    .as_ref();
-                Some((day, (range, ts)))
-            })
-            .collect();
+            .map(|row| row.map(|(day, i)| self.partitions[&day].timestamps()[i]))
+            .collect())
+    }
 
-        // Map resolved days to source indices for interleave.
-        let day_to_src: HashMap<EpochDay, usize> = resolved
-            .keys()
-            .enumerate()
-            .map(|(i, &d)| (d, i))
-            .collect();
-        let null_src = resolved.len();
+    /// Like `join_asof`, but each probe row carries its own values for every
+    /// key column.
+    fn join_asof_keyed(
+        &self,
+        probes: &RecordBatch,
+        options: &AsofOptions,
+    ) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let ts_col = timestamps(probes)?;
+        let out_schema = output_schema(&self.schema);
 
-        let indices: Vec<(usize, usize)> = ts_col
+        let key_cols = key_columns(&self.schema)
             .iter()
-            .map(|&qt| {
-                let day = EpochDay::from_timestamp_us(qt);
-                match direction {
-                    Direction::Backward => {
-                        for (&d, (range, ts)) in resolved.range(..=day).rev() {
-                            if d == day {
-                                let pos = ts[range.clone()].partition_point(|&t| t <= qt);
-                                if pos > 0 {
-                                    return (day_to_src[&d], range.start + pos - 1);
-                                }
-                            } else {
-                                return (day_to_src[&d], range.end - 1);
-                            }
-                        }
-                    }
-                    Direction::Forward => {
-                        for (&d, (range, ts)) in resolved.range(day..) {
-                            if d == day {
-                                let symbol_ts = &ts[range.clone()];
-                                let pos = symbol_ts.partition_point(|&t| t < qt);
-                                if pos < symbol_ts.len() {
-                                    return (day_to_src[&d], range.start + pos);
-                                }
-                            } else {
-                                return (day_to_src[&d], range.start);
-                            }
-                        }
-                    }
-                }
-                (null_src, 0)
+            .map(|name| {
+                probes
+                    .column_by_name(name)
+                    .and_then(|c| c.as_string_opt::<i32>())
+                    .ok_or_else(|| {
+                        arrow::error::ArrowError::SchemaError(format!(
+                            "probes must have a Utf8 {name:?} column"
+                        ))
+                    })
             })
-            .collect();
-
-        let null_arrays: Vec<ArrayRef> = out_schema
-            .fields()
-            .iter()
-            .map(|f| new_null_array(f.data_type(), 1))
-            .collect();
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let col_indices: Vec<usize> = out_schema
-            .fields()
-            .iter()
-            .map(|f| self.schema.index_of(f.name()).unwrap())
-            .collect();
+        // Group probes by key so each key's ranges are resolved once.
+        let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+        for i in 0..probes.num_rows() {
+            if key_cols.iter().any(|c| c.is_null(i)) {
+                continue;
+            }
+            let key: Vec<&str> = key_cols.iter().map(|c| c.value(i)).collect();
+            groups.entry(key.join(KEY_SEPARATOR)).or_default().push(i);
+        }
 
-        let columns: Vec<ArrayRef> = col_indices
-            .iter()
-            .enumerate()
-            .map(|(i, &col_idx)| {
-                let mut sources: Vec<&dyn arrow::array::Array> = resolved
-                    .keys()
-                    .map(|d| self.partitions[d].batch.column(col_idx).as_ref())
-                    .collect();
-                sources.push(null_arrays[i].as_ref());
-                interleave(&sources, &indices)
-            })
-            .collect::<Result<_, _>>()?;
+        let mut rows = vec![None; probes.num_rows()];
+        let mut opposite = vec![None; probes.num_rows()];
+        for (key, probe_idxs) in groups {
+            let ts: Vec<i64> = probe_idxs.iter().map(|&i| ts_col[i]).collect();
+            for (&i, row) in probe_idxs.iter().zip(self.locate_asof(&key, &ts, options, Search::Gallop, &mut AsofStats::default())?) {
+                rows[i] = row;
+            }
+            for (&i, row) in probe_idxs.iter().zip(self.locate_opposite(&key, &ts, options, Search::Gallop)?) {
+                opposite[i] = row;
+            }
+        }
 
+        let columns = self.asof_columns(&out_schema, ts_col, &rows, &opposite, options)?;
         RecordBatch::try_new(out_schema, columns)
     }
-}
 
-fn output_schema(table_schema: &SchemaRef) -> SchemaRef {
-    let fields: Vec<Field> = table_schema
-        .fields()
-        .iter()
-        .filter(|f| f.name() != SYMBOL_COL)
-        .map(|f| Field::new(f.name(), f.data_type().clone(), true))
-        .collect();
-    Arc::new(Schema::new(fields))
-}
-
-fn day_to_filename(day: EpochDay) -> String {
-    let date: jiff::civil::Date = day.into();
-    format!("{date}.arrow")
-}
-
-fn parse_day(stem: &str) -> Option<EpochDay> {
-    let date: jiff::civil::Date = stem.parse().ok()?;
-    Some(date.into())
-}
+    /// Finds the as-of row for `symbol` at each of `query_ts`, using `search`
+    /// to locate probes within a day's rows, and adds to the counters in `stats`.
+    fn locate_asof(
+        &self,
+        symbol: &str,
+        query_ts: &[i64],
+        options: &AsofOptions,
+        search: Search,
+        stats: &mut AsofStats,
+    ) -> Result<Vec<Option<(EpochDay, usize)>>, arrow::error::ArrowError> {
+        let filter = options.filter.as_ref().map(|p| self.compile_filter(p)).transpose()?;
 
-pub struct Db {
-    root: PathBuf,
-    tables: HashMap<String, Table>,
-}
+        // Pre-resolve symbol ranges once across all partitions.
+        let resolved: BTreeMap<EpochDay, (Range<usize>, &Partition)> = self
+            .partitions
+            .iter()
+            .filter_map(|(&day, part)| {
+                let range = part.symbol_index.get(symbol)?.clone();
+                Some((day, (range, part.as_ref())))
+            })
+            .collect();
+        stats.probes += query_ts.len();
+        stats.partitions += resolved.len();
+        let mut examined = 0;
 
-impl Db {
-    /// Opens a database from `root`, eagerly loading every partition into memory.
-    ///
-    /// The directory layout is `<root>/<table>/<YYYY-MM-DD>.arrow`.
-    /// Returns an empty `Db` if `root` does not exist.
-    pub fn open(root: impl AsRef<Path>) -> Result<Self, Error> {
-        let mut db = Db {
-            root: root.as_ref().to_path_buf(),
-            tables: HashMap::new(),
+        // Locate probes in time order so each partition is walked sequentially,
+        // then scatter the results back to the caller's order.
+        let order: Option<Vec<usize>> = (!query_ts.is_sorted()).then(|| {
+            let mut order: Vec<usize> = (0..query_ts.len()).collect();
+            order.sort_by_key(|&i| query_ts[i]);
+            order
+        });
+        let sorted_ts: Vec<i64>;
+        let probe_ts = match &order {
+            Some(order) => {
+                sorted_ts = order.iter().map(|&i| query_ts[i]).collect();
+                &sorted_ts[..]
+            }
+            None => query_ts,
         };
 
-        if !db.root.exists() {
-            return Ok(db);
-        }
-
-        let mut table_dirs: Vec<_> = fs::read_dir(root)?.collect::<Result<Vec<_>, _>>()?;
-        table_dirs.retain(|e| e.file_type().is_ok_and(|t| t.is_dir()));
-        table_dirs.sort_by_key(|e| e.file_name());
+        // A probe that doesn't go back in time within the same day resumes the
+        // in-day search from the previous probe's position.
+        let mut cursor: Option<(i64, EpochDay, usize)> = None;
+        let mut rows: Vec<Option<(EpochDay, usize)>> = probe_ts
+            .iter()
+            .map(|&qt| {
+                let day = self.day_of(qt);
+                if options.beyond_day_limit(day) {
+                    return None;
+                }
+                let from = match cursor {
+                    Some((pt, pday, pos)) if pt <= qt && pday == day => pos,
+                    _ => 0,
+                };
+                let in_day = resolved.get(&day).map(|(range, part)| {
+                    let ts = &part.timestamps()[range.clone()];
+                    let pos = match options.direction {
+                        Direction::Backward => search.partition_point(ts, from, |t| t <= qt),
+                        Direction::Forward => search.partition_point(ts, from, |t| t < qt),
+                    };
+                    cursor = Some((qt, day, pos));
+                    (range.start + pos, range.clone(), *part)
+                });
 
-        for table_entry in table_dirs {
-            let table_name = table_entry.file_name().to_string_lossy().into_owned();
+                let others = self
+                    .lookback_range(day, options)
+                    .map(|days| resolved.range(days).map(|(&d, (range, part))| (d, range.clone(), *part)));
+                pick_match(day, in_day, others.into_iter().flatten(), options, filter.as_ref(), &mut examined)
+            })
+            .collect();
 
-            let mut arrow_files: Vec<_> =
-                fs::read_dir(table_entry.path())?.collect::<Result<Vec<_>, _>>()?;
-            arrow_files.retain(|e| e.path().extension().is_some_and(|ext| ext == "arrow"));
-            arrow_files.sort_by_key(|e| e.file_name());
-
-            for file_entry in arrow_files {
-                let stem = file_entry
-                    .path()
-                    .file_stem()
-                    .unwrap()
-                    .to_string_lossy()
-                    .into_owned();
-                let day = parse_day(&stem).ok_or_else(|| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        format!("invalid partition date: {stem}"),
-                    )
-                })?;
-                let partition = Partition::load(&file_entry.path())?;
-                let table = db.tables.entry(table_name.clone()).or_insert_with(|| Table {
-                    schema: partition.batch.schema(),
-                    partitions: BTreeMap::new(),
-                });
-                table.partitions.insert(day, partition);
+        if let Some(order) = order {
+            let mut scattered = vec![None; rows.len()];
+            for (row, i) in rows.into_iter().zip(order) {
+                scattered[i] = row;
             }
+            rows = scattered;
         }
 
-        Ok(db)
+        if options.fill_forward {
+            for i in 1..rows.len() {
+                if rows[i].is_none() {
+                    rows[i] = rows[i - 1];
+                }
+            }
+        }
+        stats.rows_examined += examined;
+        stats.matched += rows.iter().flatten().count();
+        Ok(rows)
     }
 
-    /// Stores a record batch as a partition, writing it to disk immediately.
-    /// Replaces existing data for same table+date.
-    /// The first batch defines the table schema; subsequent batches must have matching
-    /// fields or the call returns an error.
-    pub fn ingest(&mut self, table: &str, day: EpochDay, batch: RecordBatch) -> Result<(), Error> {
-        let tbl = self.tables.entry(table.to_string()).or_insert_with(|| Table {
-            schema: batch.schema(),
-            partitions: BTreeMap::new(),
-        });
-
-        if tbl.schema.fields() != batch.schema().fields() {
-            return Err(arrow::error::ArrowError::SchemaError(format!(
-                "expected schema {:?}, got {:?}",
-                tbl.schema.fields(),
-                batch.schema().fields(),
-            ))
-            .into());
+    /// When interpolating, finds each probe's match in the direction opposite
+    /// to `options.direction`; otherwise returns nothing.
+    fn locate_opposite(
+        &self,
+        symbol: &str,
+        query_ts: &[i64],
+        options: &AsofOptions,
+        search: Search,
+    ) -> Result<Vec<Option<(EpochDay, usize)>>, arrow::error::ArrowError> {
+        match options.opposite() {
+            Some(options) => self.locate_asof(symbol, query_ts, &options, search, &mut AsofStats::default()),
+            None => Ok(Vec::new()),
         }
-
-        let partition = Partition::new(batch)?;
-        let path = self.root.join(table).join(day_to_filename(day));
-        partition.save(&path)?;
-        tbl.partitions.insert(day, partition);
-        Ok(())
+    }
+
+    /// Gathers the as-of output columns for `rows`, interpolating Float64
+    /// columns between `rows` and `opposite` if `options.interpolate` is set.
+    fn asof_columns(
+        &self,
+        schema: &SchemaRef,
+        query_ts: &[i64],
+        rows: &[Option<(EpochDay, usize)>],
+        opposite: &[Option<(EpochDay, usize)>],
+        options: &AsofOptions,
+    ) -> Result<Vec<ArrayRef>, arrow::error::ArrowError> {
+        let mut columns = self.gather(schema, rows)?;
+        if !options.interpolate {
+            return Ok(columns);
+        }
+        let (back, fwd) = match options.direction {
+            Direction::Backward => (rows, opposite),
+            Direction::Forward => (opposite, rows),
+        };
+        for (field, column) in schema.fields().iter().zip(&mut columns) {
+            if field.data_type() != &DataType::Float64 {
+                continue;
+            }
+            let col_idx = self.schema.index_of(field.name())?;
+            let value = |(day, row): (EpochDay, usize)| {
+                let part = &self.partitions[&day];
+                let col = part.batch.column(col_idx);
+                (!col.is_null(row)).then(|| (part.timestamps()[row], col.as_primitive::<Float64Type>().value(row)))
+            };
+            let values: Float64Array = query_ts
+                .iter()
+                .zip(back.iter().zip(fwd))
+                .map(|(&qt, (b, f))| {
+                    let (bt, bv) = value((*b)?)?;
+                    let (ft, fv) = value((*f)?)?;
+                    if ft == bt {
+                        return Some(bv);
+                    }
+                    Some(bv + (fv - bv) * (qt - bt) as f64 / (ft - bt) as f64)
+                })
+                .collect();
+            *column = Arc::new(values);
+        }
+        Ok(columns)
+    }
+
+    /// Returns the days other than `day` that a probe on it may fall back to,
+    /// or `None` if it may not leave its own day.
+    fn lookback_range(&self, day: EpochDay, options: &AsofOptions) -> Option<(Bound<EpochDay>, Bound<EpochDay>)> {
+        let nth = match options.lookback {
+            None => None,
+            Some(n) => {
+                let n = n.checked_sub(1)?;
+                match options.direction {
+                    Direction::Backward => self.partitions.range(..day).nth_back(n),
+                    Direction::Forward => self
+                        .partitions
+                        .range((Bound::Excluded(day), Bound::Unbounded))
+                        .nth(n),
+                }
+            }
+        };
+        let limit = match (options.direction, options.day_limit) {
+            (_, None) => None,
+            (Direction::Backward, Some(limit)) if limit >= day => return None,
+            (Direction::Forward, Some(limit)) if limit <= day => return None,
+            (_, Some(limit)) => Some(limit),
+        };
+        let bound = match (nth.map(|(&d, _)| d), limit, options.direction) {
+            (Some(d), Some(l), Direction::Backward) => Some(d.max(l)),
+            (Some(d), Some(l), Direction::Forward) => Some(d.min(l)),
+            (d, l, _) => d.or(l),
+        };
+        let bound = bound.map_or(Bound::Unbounded, Bound::Included);
+        Some(match options.direction {
+            Direction::Backward => (bound, Bound::Excluded(day)),
+            Direction::Forward => (Bound::Excluded(day), bound),
+        })
+    }
+
+    fn compile_filter(&self, predicate: &Predicate) -> Result<RowFilter, arrow::error::ArrowError> {
+        let (column, cmp) = match predicate {
+            Predicate::NotNull(column) => (column, None),
+            Predicate::Compare(column, op, value) => (column, Some((*op, *value))),
+        };
+        if key_columns(&self.schema).contains(column) {
+            return Err(arrow::error::ArrowError::SchemaError(format!(
+                "cannot filter on key column {column:?}"
+            )));
+        }
+        let col_idx = self.schema.index_of(column)?;
+        let data_type = self.schema.field(col_idx).data_type();
+        if cmp.is_some() && !matches!(data_type, DataType::Int64 | DataType::Float64) {
+            return Err(arrow::error::ArrowError::SchemaError(format!(
+                "cannot compare column {column:?} with type {data_type}"
+            )));
+        }
+        Ok(RowFilter { col_idx, cmp })
+    }
+
+    /// For each query timestamp `t`, collects every row for `symbol` with a
+    /// timestamp in `[t - window_us, t]`.
+    fn join_window(
+        &self,
+        symbol: &str,
+        query_ts: &RecordBatch,
+        window_us: i64,
+    ) -> Result<WindowJoin, arrow::error::ArrowError> {
+        let ts_col = timestamps(query_ts)?;
+        let out_schema = output_schema(&self.schema);
+
+        let mut offsets = Vec::with_capacity(ts_col.len() + 1);
+        offsets.push(0);
+        let mut rows: Vec<Option<(EpochDay, usize)>> = Vec::new();
+        for &qt in ts_col.iter() {
+            for (day, _, range) in self.scan(symbol, qt.saturating_sub(window_us)..qt.saturating_add(1)) {
+                rows.extend(range.map(|i| Some((day, i))));
+            }
+            offsets.push(rows.len());
+        }
+
+        let columns = self.gather(&out_schema, &rows)?;
+        Ok(WindowJoin {
+            offsets,
+            rows: RecordBatch::try_new(out_schema, columns)?,
+        })
+    }
+
+    /// For each query timestamp, collects up to `n` of the most recent rows for
+    /// `symbol` at or before it, walking back through earlier partitions.
+    fn join_last_n(
+        &self,
+        symbol: &str,
+        query_ts: &RecordBatch,
+        n: usize,
+    ) -> Result<WindowJoin, arrow::error::ArrowError> {
+        let ts_col = timestamps(query_ts)?;
+        let out_schema = output_schema(&self.schema);
+
+        let mut offsets = Vec::with_capacity(ts_col.len() + 1);
+        offsets.push(0);
+        let mut rows: Vec<Option<(EpochDay, usize)>> = Vec::new();
+        for &qt in ts_col.iter() {
+            let start = rows.len();
+            let day = self.day_of(qt);
+            for (&d, part) in self.partitions.range(..=day).rev() {
+                let remaining = n - (rows.len() - start);
+                if remaining == 0 {
+                    break;
+                }
+                let Some(range) = part.symbol_index.get(symbol) else {
+                    continue;
+                };
+                let end = if d == day {
+                    range.start + part.timestamps()[range.clone()].partition_point(|&t| t <= qt)
+                } else {
+                    range.end
+                };
+                let take = remaining.min(end - range.start);
+                rows.extend((end - take..end).rev().map(|i| Some((d, i))));
+            }
+            // Collected newest-first; present each probe's rows in time order.
+            rows[start..].reverse();
+            offsets.push(rows.len());
+        }
+
+        let columns = self.gather(&out_schema, &rows)?;
+        Ok(WindowJoin {
+            offsets,
+            rows: RecordBatch::try_new(out_schema, columns)?,
+        })
+    }
+
+    /// As-of joins `column` for each of `symbols` at every query timestamp,
+    /// one output column per symbol.
+    fn pivot(
+        &self,
+        symbols: &[&str],
+        query_ts: &RecordBatch,
+        column: &str,
+        options: &AsofOptions,
+    ) -> Result<RecordBatch, arrow::error::ArrowError> {
+        if key_columns(&self.schema).iter().any(|k| k == column) {
+            return Err(arrow::error::ArrowError::SchemaError(format!(
+                "cannot pivot key column {column:?}"
+            )));
+        }
+        let field = self.schema.field_with_name(column)?;
+        let value_schema = Arc::new(Schema::new(vec![field.clone().with_nullable(true)]));
+        let ts_col = timestamps(query_ts)?;
+
+        let mut fields = vec![Field::new(TIMESTAMP_COL, DataType::Int64, false)];
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(ts_col.to_vec()))];
+        for &symbol in symbols {
+            let rows = self.locate_asof(symbol, ts_col, options, Search::Gallop, &mut AsofStats::default())?;
+            let opposite = self.locate_opposite(symbol, ts_col, options, Search::Gallop)?;
+            let mut values = self.asof_columns(&value_schema, ts_col, &rows, &opposite, options)?;
+            fields.push(Field::new(symbol, field.data_type().clone(), true));
+            columns.push(values.remove(0));
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+
+    /// Returns the backward as-of row at `ts` for every symbol present in the
+    /// latest partition on or before `ts`'s day, sorted by symbol.
+    fn snapshot_at(&self, ts: i64, lookback: Option<usize>) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let day = self.day_of(ts);
+        let mut symbols: Vec<&str> = match self.partitions.range(..=day).next_back() {
+            Some((_, part)) => part.symbol_index.keys().map(String::as_str).collect(),
+            None => Vec::new(),
+        };
+        symbols.sort_unstable();
+        let options = AsofOptions { lookback, ..AsofOptions::new(Direction::Backward) };
+        self.join_asof_broadcast(&symbols, ts, &options)
+    }
+
+    /// As-of joins each of `symbols` at the single timestamp `ts`, resolving
+    /// the probe's partition and lookback range once for all of them.
+    fn join_asof_broadcast(
+        &self,
+        symbols: &[&str],
+        ts: i64,
+        options: &AsofOptions,
+    ) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let rows = self.locate_broadcast(symbols, ts, options)?;
+        let opposite = match options.opposite() {
+            Some(options) => self.locate_broadcast(symbols, ts, &options)?,
+            None => Vec::new(),
+        };
+
+        let out_schema = output_schema(&self.schema);
+        let mut fields = vec![Arc::new(Field::new(SYMBOL_COL, DataType::Utf8, false))];
+        fields.extend(out_schema.fields().iter().cloned());
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(StringArray::from(symbols.to_vec()))];
+        columns.extend(self.asof_columns(&out_schema, &vec![ts; symbols.len()], &rows, &opposite, options)?);
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+
+    /// Finds the as-of row for each of `symbols` at `ts`. Rather than resolving
+    /// every partition per symbol as `locate_asof` does, earlier (or later)
+    /// partitions are only walked as far as each symbol needs.
+    fn locate_broadcast(
+        &self,
+        symbols: &[&str],
+        ts: i64,
+        options: &AsofOptions,
+    ) -> Result<Vec<Option<(EpochDay, usize)>>, arrow::error::ArrowError> {
+        let filter = options.filter.as_ref().map(|p| self.compile_filter(p)).transpose()?;
+        let day = self.day_of(ts);
+        if options.beyond_day_limit(day) {
+            return Ok(vec![None; symbols.len()]);
+        }
+        let today = self.partitions.get(&day).map(Arc::as_ref);
+        let others = self.lookback_range(day, options);
+
+        let mut examined = 0;
+        Ok(symbols
+            .iter()
+            .map(|&symbol| {
+                let in_day = today.and_then(|part| {
+                    let range = part.symbol_index.get(symbol)?.clone();
+                    let day_ts = &part.timestamps()[range.clone()];
+                    let pos = match options.direction {
+                        Direction::Backward => day_ts.partition_point(|&t| t <= ts),
+                        Direction::Forward => day_ts.partition_point(|&t| t < ts),
+                    };
+                    Some((range.start + pos, range, part))
+                });
+                let others = others.map(|days| {
+                    self.partitions
+                        .range(days)
+                        .filter_map(|(&d, part)| Some((d, part.symbol_index.get(symbol)?.clone(), part.as_ref())))
+                });
+                pick_match(day, in_day, others.into_iter().flatten(), options, filter.as_ref(), &mut examined)
+            })
+            .collect())
+    }
+
+    /// Yields, in time order, each partition's rows for `symbol` whose
+    /// timestamps fall in `range`.
+    fn scan<'a>(
+        &'a self,
+        symbol: &'a str,
+        range: Range<i64>,
+    ) -> impl DoubleEndedIterator<Item = (EpochDay, &'a Partition, Range<usize>)> + 'a {
+        self.partitions_in(range.clone())
+            .filter_map(move |(&day, part)| Some((day, part, part.rows_in(symbol, &range)?)))
+    }
+
+    /// Yields the rows of all `symbols` in the partitions overlapping `range`,
+    /// merged by timestamp, in batches of at most [`SCAN_BATCH_ROWS`] rows
+    /// that never span partitions; equal timestamps keep the order of
+    /// `symbols`. `None` means every key, in sorted order.
+    fn merge_scan<'a>(
+        self: Arc<Self>,
+        symbols: Option<&'a [&'a str]>,
+        range: Range<i64>,
+    ) -> impl Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>> + 'a {
+        let value_schema = output_schema(&self.schema);
+        let mut fields = vec![Arc::new(Field::new(SYMBOL_COL, DataType::Utf8, false))];
+        fields.extend(value_schema.fields().iter().cloned());
+        let schema = Arc::new(Schema::new(fields));
+
+        // Owning the table lets the scan outlive the `Db` lock it came from.
+        let days: Vec<EpochDay> = self.partitions_in(range.clone()).map(|(&day, _)| day).collect();
+        MergeScan {
+            table: self,
+            symbols,
+            range,
+            value_schema,
+            schema,
+            days: days.into_iter(),
+            day: None,
+            keys: Vec::new(),
+            heads: BinaryHeap::new(),
+        }
+    }
+
+    /// Yields the partitions whose day overlaps the timestamp `range`.
+    fn partitions_in(
+        &self,
+        range: Range<i64>,
+    ) -> impl DoubleEndedIterator<Item = (&EpochDay, &Partition)> {
+        let parts = (range.start < range.end).then(|| {
+            let first = self.day_of(range.start);
+            let last = self.day_of(range.end - 1);
+            self.partitions.range(first..=last)
+        });
+        parts.into_iter().flatten().map(|(day, part)| (day, part.as_ref()))
+    }
+
+    /// Returns up to `n` rows for `symbol` in `range`, the earliest if
+    /// `from_end` is false and the latest otherwise, in time order.
+    fn head_tail(
+        &self,
+        symbol: &str,
+        range: Range<i64>,
+        n: usize,
+        from_end: bool,
+    ) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let mut rows: Vec<Option<(EpochDay, usize)>> = Vec::new();
+        if from_end {
+            for (day, _, part_rows) in self.scan(symbol, range).rev() {
+                let take = part_rows.len().min(n - rows.len());
+                rows.extend(part_rows.rev().take(take).map(|i| Some((day, i))));
+                if rows.len() == n {
+                    break;
+                }
+            }
+            rows.reverse();
+        } else {
+            for (day, _, part_rows) in self.scan(symbol, range) {
+                let take = part_rows.len().min(n - rows.len());
+                rows.extend(part_rows.take(take).map(|i| Some((day, i))));
+                if rows.len() == n {
+                    break;
+                }
+            }
+        }
+        let out_schema = output_schema(&self.schema);
+        let columns = self.gather(&out_schema, &rows)?;
+        RecordBatch::try_new(out_schema, columns)
+    }
+
+    /// Returns the rows for `symbol` in `range`, in time order, with the
+    /// columns of `schema`.
+    fn range(&self, symbol: &str, range: Range<i64>, schema: &SchemaRef) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let rows: Vec<Option<(EpochDay, usize)>> = self
+            .scan(symbol, range)
+            .flat_map(|(day, _, part_rows)| part_rows.map(move |i| Some((day, i))))
+            .collect();
+        let columns = self.gather(schema, &rows)?;
+        RecordBatch::try_new(schema.clone(), columns)
+    }
+
+    /// Returns the rows for `symbol` in `range` picked by `stride`, in time order.
+    fn sample(&self, symbol: &str, range: Range<i64>, stride: Stride) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let mut rows: Vec<Option<(EpochDay, usize)>> = Vec::new();
+        match stride {
+            Stride::Rows(n) => {
+                let mut seen = 0;
+                for (day, _, part_rows) in self.scan(symbol, range) {
+                    let skip = (n - seen % n) % n;
+                    seen += part_rows.len();
+                    rows.extend(part_rows.skip(skip).step_by(n).map(|i| Some((day, i))));
+                }
+            }
+            Stride::Interval(interval_us) => {
+                let mut next_bucket = i64::MIN;
+                for (day, part, part_rows) in self.scan(symbol, range) {
+                    let ts = part.timestamps();
+                    let mut i = part_rows.start;
+                    loop {
+                        i += ts[i..part_rows.end].partition_point(|&t| t < next_bucket);
+                        if i == part_rows.end {
+                            break;
+                        }
+                        rows.push(Some((day, i)));
+                        next_bucket = ts[i].div_euclid(interval_us).saturating_add(1).saturating_mul(interval_us);
+                    }
+                }
+            }
+        }
+        let out_schema = output_schema(&self.schema);
+        let columns = self.gather(&out_schema, &rows)?;
+        RecordBatch::try_new(out_schema, columns)
+    }
+
+    /// Materializes the columns of `schema` for the given `(day, row)`
+    /// locations, producing nulls where the location is `None`.
+    fn gather(
+        &self,
+        schema: &SchemaRef,
+        rows: &[Option<(EpochDay, usize)>],
+    ) -> Result<Vec<ArrayRef>, arrow::error::ArrowError> {
+        // Map touched days to source indices for interleave.
+        let mut days: Vec<EpochDay> = rows.iter().flatten().map(|&(d, _)| d).collect();
+        days.sort_unstable();
+        days.dedup();
+        let null_src = days.len();
+
+        let indices: Vec<(usize, usize)> = rows
+            .iter()
+            .map(|row| match row {
+                Some((d, i)) => (days.binary_search(d).unwrap(), *i),
+                None => (null_src, 0),
+            })
+            .collect();
+
+        schema
+            .fields()
+            .iter()
+            .map(|f| {
+                let col_idx = self.schema.index_of(f.name())?;
+                let null_array = new_null_array(f.data_type(), 1);
+                let mut sources: Vec<&dyn arrow::array::Array> = days
+                    .iter()
+                    .map(|d| self.partitions[d].batch.column(col_idx).as_ref())
+                    .collect();
+                sources.push(null_array.as_ref());
+                interleave(&sources, &indices)
+            })
+            .collect()
+    }
+}
+
+/// Rows per batch of [`Db::scan`] and [`Db::replay`].
+const SCAN_BATCH_ROWS: usize = 64 * 1024;
+
+/// The iterator of [`Table::merge_scan`]: a k-way merge of the keys' runs of
+/// rows in one partition at a time, so memory stays bounded by the batch size
+/// and the number of keys rather than the partition's rows.
+struct MergeScan<'a> {
+    table: Arc<Table>,
+    symbols: Option<&'a [&'a str]>,
+    range: Range<i64>,
+    value_schema: SchemaRef,
+    schema: SchemaRef,
+    /// The partitions yet to merge.
+    days: std::vec::IntoIter<EpochDay>,
+    /// The partition being merged and its keys to scan.
+    day: Option<EpochDay>,
+    keys: Vec<String>,
+    /// Per key with rows left: the timestamp of its next row, its index in
+    /// `keys`, that row and the end of its rows.
+    heads: BinaryHeap<Reverse<(i64, usize, usize, usize)>>,
+}
+
+impl Iterator for MergeScan<'_> {
+    type Item = Result<RecordBatch, arrow::error::ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.heads.is_empty() {
+            let day = self.days.next()?;
+            let part = &self.table.partitions[&day];
+            self.keys = match self.symbols {
+                Some(symbols) => symbols.iter().map(|symbol| symbol.to_string()).collect(),
+                None => {
+                    let mut all: Vec<String> = part.symbol_index.keys().cloned().collect();
+                    all.sort_unstable();
+                    all
+                }
+            };
+            let ts = part.timestamps();
+            for (k, key) in self.keys.iter().enumerate() {
+                if let Some(rows) = part.rows_in(key, &self.range).filter(|rows| !rows.is_empty()) {
+                    self.heads.push(Reverse((ts[rows.start], k, rows.start, rows.end)));
+                }
+            }
+            self.day = Some(day);
+        }
+
+        let day = self.day.unwrap();
+        let ts = self.table.partitions[&day].timestamps();
+        let mut keys = Vec::new();
+        let mut rows = Vec::new();
+        while rows.len() < SCAN_BATCH_ROWS
+            && let Some(Reverse((_, k, row, end))) = self.heads.pop()
+        {
+            keys.push(k);
+            rows.push(Some((day, row)));
+            if row + 1 < end {
+                self.heads.push(Reverse((ts[row + 1], k, row + 1, end)));
+            }
+        }
+
+        let symbol_col: StringArray = keys.iter().map(|&k| Some(self.keys[k].as_str())).collect();
+        let batch = self.table.gather(&self.value_schema, &rows).and_then(|values| {
+            let mut columns: Vec<ArrayRef> = vec![Arc::new(symbol_col)];
+            columns.extend(values);
+            RecordBatch::try_new(self.schema.clone(), columns)
+        });
+        Some(batch)
+    }
+}
+
+/// The result of [`Db::join_window`] and [`Db::join_last_n`]: the rows matched by probe `i` are
+/// `rows.slice(offsets[i], offsets[i + 1] - offsets[i])`, in time order.
+#[derive(Debug, Clone)]
+pub struct WindowJoin {
+    pub offsets: Vec<usize>,
+    pub rows: RecordBatch,
+}
+
+/// Builds a probe batch covering every `interval_us` step of `range` for each
+/// of `symbols`: a Utf8 `symbol` column and an Int64 `timestamp` column, grouped
+/// by symbol with timestamps ascending.
+///
+/// The result can be passed to [`Db::join_asof_keyed`] directly, or to
+/// [`Db::join_asof`] when `symbols` has a single entry.
+pub fn probe_grid(symbols: &[&str], range: Range<i64>, interval_us: i64) -> Result<RecordBatch, Error> {
+    if interval_us <= 0 {
+        return Err(Error::InvalidInterval(interval_us));
+    }
+    let steps: Vec<i64> = (range.start..range.end).step_by(interval_us as usize).collect();
+    let symbol_col: StringArray = symbols
+        .iter()
+        .flat_map(|s| std::iter::repeat_n(Some(*s), steps.len()))
+        .collect();
+    let ts_col = Int64Array::from_iter_values(symbols.iter().flat_map(|_| steps.iter().copied()));
+    let schema = Schema::new(vec![
+        Field::new(SYMBOL_COL, DataType::Utf8, false),
+        Field::new(TIMESTAMP_COL, DataType::Int64, false),
+    ]);
+    Ok(RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(symbol_col), Arc::new(ts_col)],
+    )?)
+}
+
+/// The result of [`Db::asof_tables`]: row `i` of `right` is the match for row
+/// `i` of `left`.
+///
+/// `left` has a Utf8 `symbol` column holding the key followed by the left
+/// table's other columns; `right` has the nullable columns of [`Db::join_asof`].
+#[derive(Debug, Clone)]
+pub struct TableJoin {
+    pub left: RecordBatch,
+    pub right: RecordBatch,
+}
+
+fn output_schema(table_schema: &SchemaRef) -> SchemaRef {
+    let keys = key_columns(table_schema);
+    let fields: Vec<Field> = table_schema
+        .fields()
+        .iter()
+        .filter(|f| !keys.contains(f.name()))
+        .map(|f| Field::new(f.name(), f.data_type().clone(), true))
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+/// A table directory's list of staged files still to be moved into place by a
+/// multi-partition write, one `<staged>\t<partition>` line per file.
+const JOURNAL: &str = ".journal";
+
+/// The file in the root directory that processes with the database open for
+/// writing hold a shared lock on.
+const LOCK: &str = ".lock";
+
+/// Moves `staged` partition files into place in the table directory `dir`.
+/// Several are first listed in a [`JOURNAL`], which [`Db::open`] replays after
+/// a crash, so the write lands all or nothing.
+fn commit(dir: &Path, staged: Vec<(tempfile::TempPath, PathBuf)>, durability: Durability) -> Result<(), Error> {
+    let journaled = staged.len() > 1;
+    if journaled {
+        let mut lines = String::new();
+        for (tmp, path) in &staged {
+            let name = |p: &Path| p.file_name().unwrap().to_string_lossy().into_owned();
+            lines.push_str(&format!("{}\t{}\n", name(tmp), name(path)));
+        }
+        let pending = dir.join(format!("{JOURNAL}.tmp"));
+        fs::write(&pending, lines)?;
+        if durability == Durability::Always {
+            File::open(&pending)?.sync_all()?;
+        }
+        fs::rename(&pending, dir.join(JOURNAL))?;
+        sync_dir(dir, durability)?;
+    }
+    for (tmp, path) in staged {
+        replace(&tmp, &path)?;
+        // Moved, so there is nothing left for `tmp` to delete.
+        let _ = tmp.keep();
+    }
+    sync_dir(dir, durability)?;
+    if journaled {
+        fs::remove_file(dir.join(JOURNAL))?;
+    }
+    Ok(())
+}
+
+/// Renames `from` to the partition file `path`, replacing the file there.
+/// Windows can't replace a memory-mapped file, as a partition's is while
+/// queries may read it, so there it is first moved aside to a hidden `.old`
+/// file, deleted once unmapped; [`restore_replaced`] deals with those left.
+fn replace(from: &Path, path: &Path) -> std::io::Result<()> {
+    let Err(e) = fs::rename(from, path) else {
+        return Ok(());
+    };
+    if !cfg!(windows) || !path.exists() {
+        return Err(e);
+    }
+    let dir = path.parent().expect("partition path must have a parent");
+    let stem = path.file_stem().expect("partition path must have a name").to_string_lossy();
+    let aside = tempfile::Builder::new().prefix(&format!(".{stem}.old.")).tempfile_in(dir)?;
+    let aside = aside.into_temp_path().keep().map_err(|e| e.error)?;
+    fs::rename(path, &aside)?;
+    fs::rename(from, path)?;
+    // Fails while the file is mapped, leaving it for the next `Db::open`.
+    let _ = fs::remove_file(aside);
+    Ok(())
+}
+
+/// Deletes the files [`replace`] moved aside in the table directory `dir`
+/// but couldn't delete, or moves the newest back if a crash in between left
+/// its day without a partition file. Those still mapped, by another process,
+/// are left for next time.
+fn restore_replaced(dir: &Path) -> Result<(), Error> {
+    let mut old = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(day) = name.strip_prefix('.').and_then(|name| name.split_once(".old.")).and_then(|(stem, _)| parse_day(stem)) else {
+            continue;
+        };
+        old.push((day, entry.metadata()?.modified()?, entry.path()));
+    }
+    // Newest first, as renaming keeps the time each version was written.
+    old.sort_by_key(|(_, modified, _)| Reverse(*modified));
+    for (day, _, path) in old {
+        let partition = dir.join(day_to_filename(day));
+        if partition.exists() {
+            let _ = fs::remove_file(path);
+        } else {
+            fs::rename(path, partition)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replays an interrupted [`commit`] in the table directory `dir`, returning
+/// the days it completed.
+fn replay_journal(dir: &Path, durability: Durability) -> Result<Vec<EpochDay>, Error> {
+    // A journal never moved into place means the commit never started.
+    let pending = dir.join(format!("{JOURNAL}.tmp"));
+    if pending.exists() {
+        fs::remove_file(pending)?;
+    }
+    let journal = dir.join(JOURNAL);
+    if !journal.exists() {
+        return Ok(Vec::new());
+    }
+    let mut days = Vec::new();
+    for line in fs::read_to_string(&journal)?.lines() {
+        let (tmp, name) = line.split_once('\t').expect("corrupt journal");
+        let day = parse_day(name.strip_suffix(".arrow").expect("corrupt journal")).expect("corrupt journal");
+        // Files already moved before the crash are gone from their staged path.
+        if dir.join(tmp).exists() {
+            replace(&dir.join(tmp), &dir.join(name))?;
+        }
+        days.push(day);
+    }
+    sync_dir(dir, durability)?;
+    fs::remove_file(&journal)?;
+    Ok(days)
+}
+
+/// Makes renames in `dir` durable. Windows can't open directories to sync,
+/// so there a crash may still undo the latest renames.
+fn sync_dir(dir: &Path, durability: Durability) -> Result<(), Error> {
+    if cfg!(unix) && durability == Durability::Always {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// The table directories under `root`, by name.
+fn table_dirs(root: &Path) -> Result<Vec<fs::DirEntry>, Error> {
+    let mut dirs: Vec<_> = fs::read_dir(root)?.collect::<Result<Vec<_>, _>>()?;
+    // Hidden directories are staged tables, e.g. of `copy_table`, that a
+    // crash left behind.
+    dirs.retain(|e| e.file_type().is_ok_and(|t| t.is_dir()) && !e.file_name().to_string_lossy().starts_with('.'));
+    dirs.sort_by_key(|e| e.file_name());
+    Ok(dirs)
+}
+
+/// Deletes the hidden directories in `root` that a crash left behind, where
+/// [`Db::drop_table`] moves a table before deleting it and [`Db::copy_table`]
+/// stages a copy.
+fn remove_staged_tables(root: &Path) -> Result<(), Error> {
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Named `.{table}.{random}` by `tempfile`.
+        let staged = name.strip_prefix('.').and_then(|name| name.rsplit_once('.')).is_some_and(|(table, _)| Db::check_table_name(table).is_ok());
+        if staged && entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the partitions in the table directory `dir` that `table` doesn't
+/// already have, returning a copy of `table` (or a new table) with them and
+/// their days, or `None` if there are none.
+fn load_partitions(dir: &Path, mmap: bool, table: Option<&Table>) -> Result<Option<(Table, Vec<EpochDay>)>, Error> {
+    let mut arrow_files: Vec<_> = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    arrow_files.retain(|e| e.path().extension().is_some_and(|ext| ext == "arrow"));
+    arrow_files.sort_by_key(|e| e.file_name());
+
+    let mut table = table.cloned();
+    let mut days = Vec::new();
+    for file_entry in arrow_files {
+        let stem = file_entry
+            .path()
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let day = parse_day(&stem).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid partition date: {stem}"),
+            )
+        })?;
+        if table.as_ref().is_some_and(|t| t.partitions.contains_key(&day)) {
+            continue;
+        }
+        let partition = Partition::load(&file_entry.path(), mmap)?;
+        let table = table.get_or_insert_with(|| Table::new(partition.batch.schema()).expect("corrupt on-disk schema"));
+        table.partitions.insert(day, Arc::new(partition));
+        days.push(day);
+    }
+    Ok(table.filter(|_| !days.is_empty()).map(|table| (table, days)))
+}
+
+/// The day of a partition file outside the database, from its name.
+fn partition_day(path: &Path) -> Result<EpochDay, Error> {
+    let day = path
+        .file_name()
+        .and_then(|name| name.to_str()?.strip_suffix(".arrow"))
+        .and_then(parse_day)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("not a partition file name: {}", path.display()),
+            )
+        })?;
+    Ok(day)
+}
+
+/// Creates a temporary file beside the partition file `path`, named after it
+/// so that `Db::open` can tell which write left it behind.
+fn staging_file(path: &Path) -> Result<tempfile::NamedTempFile, Error> {
+    let parent = path.parent().expect("partition path must have a parent");
+    let prefix = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+    Ok(tempfile::Builder::new().prefix(&prefix).tempfile_in(parent)?)
+}
+
+/// Passes writes through to `W`, keeping the CRC-32 of the bytes written.
+struct CrcWriter<W> {
+    inner: W,
+    crc: crc32fast::Hasher,
+}
+
+impl<W> CrcWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, crc: crc32fast::Hasher::new() }
+    }
+}
+
+impl<W: std::io::Write> std::io::Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn day_to_filename(day: EpochDay) -> String {
+    let date: jiff::civil::Date = day.into();
+    format!("{date}.arrow")
+}
+
+fn parse_day(stem: &str) -> Option<EpochDay> {
+    let date: jiff::civil::Date = stem.parse().ok()?;
+    Some(date.into())
+}
+
+/// The hidden file beside the partition file `path` recording its checksum,
+/// so that reopening the database needn't read every file for it.
+fn checksum_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().expect("partition path must have a name").to_string_lossy();
+    path.with_file_name(format!(".{stem}.crc"))
+}
+
+/// The size and modification time of a file, which tell whether a checksum
+/// recorded for the file at `path` is of the file there now: partition files
+/// are replaced, never modified.
+fn file_version(metadata: &fs::Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(format!("{} {}", metadata.len(), modified.as_nanos()))
+}
+
+/// Records `checksum` for the file with `metadata` that will be at `path`.
+/// Best effort: a record lost to a crash is only recomputed.
+fn save_checksum(path: &Path, metadata: &fs::Metadata, checksum: u32) -> Result<(), Error> {
+    if let Some(version) = file_version(metadata) {
+        fs::write(checksum_path(path), format!("{checksum} {version}"))?;
+    }
+    Ok(())
+}
+
+/// The checksum [`save_checksum`] recorded for the file with `metadata` at
+/// `path`, if it did for this version of it.
+fn saved_checksum(path: &Path, metadata: &fs::Metadata) -> Option<u32> {
+    let saved = fs::read_to_string(checksum_path(path)).ok()?;
+    let (checksum, version) = saved.split_once(' ')?;
+    if Some(version) != file_version(metadata).as_deref() {
+        return None;
+    }
+    checksum.parse().ok()
+}
+
+/// Options for [`Db::open_with_options`].
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    /// Reject writes: [`Db::ingest`] and [`Db::downsample`] return
+    /// [`Error::ReadOnly`].
+    pub read_only: bool,
+    /// Memory-map partition files rather than reading them into memory.
+    /// Reading is safer where files may be truncated underneath the process,
+    /// e.g. on network filesystems, at the cost of resident memory.
+    ///
+    /// Off by default on Windows, which can't delete a mapped file: the old
+    /// file of a day rewritten while mapped stays on disk, hidden, until the
+    /// database is next opened.
+    pub mmap: bool,
+    pub durability: Durability,
+    /// The most disk space any one table's partitions may take up. A write
+    /// that would exceed it fails with [`Error::QuotaExceeded`], leaving the
+    /// table unchanged.
+    pub max_table_bytes: Option<u64>,
+    /// The [`AsofOptions::lookback`] of the joins that take no options,
+    /// [`Db::join_asof`] and [`Db::snapshot_at`].
+    pub lookback: Option<usize>,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            mmap: !cfg!(windows),
+            durability: Durability::Always,
+            max_table_bytes: None,
+            lookback: None,
+        }
+    }
+}
+
+/// What [`Db::open_with_report`] found left behind by a crash.
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryReport {
+    /// The (table, day) of each partition write that never completed. The
+    /// partition kept its previous contents, if any, so the ingest needs
+    /// replaying.
+    pub interrupted: Vec<(String, EpochDay)>,
+    /// The (table, day) of each partition of an interrupted
+    /// [`Db::ingest_days`] that was finished from its journal. Not done when
+    /// opened read-only, which may then see only part of that write.
+    pub completed: Vec<(String, EpochDay)>,
+}
+
+/// What a call to [`Db::ingest`] wrote.
+#[derive(Debug, Clone, Default)]
+pub struct WriteReport {
+    /// Rows in the ingested batch.
+    pub rows: usize,
+    /// Distinct keys in the ingested batch.
+    pub symbols: usize,
+    /// Size of the partition file, or of its arrays for an in-memory `Db`.
+    pub bytes: u64,
+    /// Whether the day had no partition before, so one was created.
+    pub created: bool,
+    /// Whether the rows were merged into the day's existing partition, under
+    /// [`WriteMode::Append`]. An existing partition is otherwise replaced.
+    pub merged: bool,
+}
+
+/// A table, as listed by [`Db::tables`].
+#[derive(Debug, Clone)]
+pub struct TableInfo {
+    pub name: String,
+    pub schema: SchemaRef,
+    /// The first and last days with a partition; `None` if there are none.
+    pub days: Option<RangeInclusive<EpochDay>>,
+}
+
+/// A partition, as listed by [`Db::partitions`].
+#[derive(Debug, Clone)]
+pub struct PartitionInfo {
+    pub table: String,
+    pub day: EpochDay,
+    pub rows: usize,
+    /// Size of the partition file, or of its arrays for an in-memory `Db`.
+    pub bytes: u64,
+    /// The CRC-32 of the partition's file, so equal for a copy of it, e.g. on
+    /// a replica. Computed on first listing for files this `Db` didn't write.
+    pub checksum: u32,
+}
+
+/// A database of tables, safe to share across threads, e.g. in an `Arc`.
+///
+/// Queries take only a snapshot of the table they read, so they run
+/// concurrently with each other and with writes, which replace a table
+/// snapshot once the new partition is on disk. Writes to the same table are
+/// serialized; writes to different tables run concurrently.
+pub struct Db {
+    /// `None` for [`Db::open_in_memory`].
+    root: Option<PathBuf>,
+    options: DbOptions,
+    tables: RwLock<HashMap<String, Arc<Table>>>,
+    /// Per table, held for the duration of each write to it; see
+    /// [`Db::writer`].
+    writers: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// A shared lock on [`LOCK`] while open for writing, telling other
+    /// processes opening `root` that writes may be in progress.
+    _lock: Option<File>,
+}
+
+impl Db {
+    /// Opens a database from `root`, eagerly loading every partition into memory.
+    ///
+    /// The directory layout is `<root>/<table>/<YYYY-MM-DD>.arrow`.
+    /// Returns an empty `Db` if `root` does not exist, creating it unless
+    /// opened read-only.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with_options(root, DbOptions::default())
+    }
+
+    /// Like [`Db::open`], with additional [`DbOptions`].
+    pub fn open_with_options(root: impl AsRef<Path>, options: DbOptions) -> Result<Self, Error> {
+        Ok(Self::open_with_report(root, options)?.0)
+    }
+
+    /// Opens an empty database that keeps its partitions in memory only, with
+    /// no files, e.g. for tests or ephemeral caches. Everything else behaves as
+    /// for a database on disk; [`Db::refresh`] finds nothing.
+    pub fn open_in_memory() -> Self {
+        Db {
+            root: None,
+            options: DbOptions::default(),
+            tables: RwLock::new(HashMap::new()),
+            writers: Mutex::default(),
+            _lock: None,
+        }
+    }
+
+    /// Like [`Db::open_with_options`], also reporting writes that a crash
+    /// interrupted. Their temporary files are removed unless the database is
+    /// opened read-only.
+    ///
+    /// While another process has `root` open for writing, its writes may be
+    /// in progress rather than interrupted, so they are left alone and not
+    /// reported; the last process to open it then recovers them.
+    pub fn open_with_report(
+        root: impl AsRef<Path>,
+        options: DbOptions,
+    ) -> Result<(Self, RecoveryReport), Error> {
+        let root = root.as_ref().to_path_buf();
+        let lock = if options.read_only {
+            None
+        } else {
+            fs::create_dir_all(&root)?;
+            Some(File::options().create(true).truncate(false).write(true).open(root.join(LOCK))?)
+        };
+        // An exclusive lock means no other writer is live. It is then
+        // traded for a shared one, held for the life of the `Db`.
+        let recover = match lock.as_ref().map(File::try_lock) {
+            Some(Ok(())) => true,
+            Some(Err(TryLockError::WouldBlock)) | None => false,
+            Some(Err(TryLockError::Error(e))) => return Err(e.into()),
+        };
+        if let Some(lock) = &lock {
+            lock.unlock()?;
+            lock.lock_shared()?;
+        }
+        let mut db = Db {
+            root: Some(root.clone()),
+            options,
+            tables: RwLock::new(HashMap::new()),
+            writers: Mutex::default(),
+            _lock: lock,
+        };
+        let mut report = RecoveryReport::default();
+
+        if !root.exists() {
+            return Ok((db, report));
+        }
+
+        if recover {
+            remove_staged_tables(&root)?;
+        }
+        let tables = db.tables.get_mut().unwrap();
+        for table_entry in table_dirs(&root)? {
+            let table_name = table_entry.file_name().to_string_lossy().into_owned();
+
+            if recover {
+                restore_replaced(&table_entry.path())?;
+                for day in replay_journal(&table_entry.path(), db.options.durability)? {
+                    report.completed.push((table_name.clone(), day));
+                }
+            }
+
+            for entry in fs::read_dir(table_entry.path())? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let Some(day) = name
+                    .strip_prefix('.')
+                    .and_then(|name| name.split_once(".arrow."))
+                    .and_then(|(stem, _)| parse_day(stem))
+                else {
+                    continue;
+                };
+                if recover {
+                    fs::remove_file(entry.path())?;
+                } else if !db.options.read_only {
+                    continue;
+                }
+                report.interrupted.push((table_name.clone(), day));
+            }
+
+            if let Some((table, _)) = load_partitions(&table_entry.path(), db.options.mmap, None)? {
+                tables.insert(table_name, Arc::new(table));
+            }
+        }
+
+        Ok((db, report))
+    }
+
+    /// Registers the partitions, and tables, that another process has written
+    /// since this `Db` was opened or last refreshed — e.g. a query server
+    /// following a separate ingest process. Returns the (table, day) of each.
+    ///
+    /// Partitions already loaded are kept as they are, so one that the other
+    /// process rewrote is only seen after reopening.
+    pub fn refresh(&self) -> Result<Vec<(String, EpochDay)>, Error> {
+        let Some(root) = self.root.as_ref().filter(|root| root.exists()) else {
+            return Ok(Vec::new());
+        };
+        let mut added = Vec::new();
+        for entry in table_dirs(root)? {
+            let table = entry.file_name().to_string_lossy().into_owned();
+            for day in self.refresh_table(&table)? {
+                added.push((table.clone(), day));
+            }
+        }
+        Ok(added)
+    }
+
+    /// Like [`Db::refresh`] for the one table `table`, which needn't have
+    /// existed before. Returns the days registered.
+    pub fn refresh_table(&self, table: &str) -> Result<Vec<EpochDay>, Error> {
+        Self::check_table_name(table)?;
+        let writer = self.writer(table);
+        let _writer = writer.lock().unwrap();
+        let Some(dir) = self.root.as_ref().map(|root| root.join(table)).filter(|dir| dir.is_dir()) else {
+            return Ok(Vec::new());
+        };
+        let current = self.tables.read().unwrap().get(table).cloned();
+        let Some((tbl, days)) = load_partitions(&dir, self.options.mmap, current.as_deref())? else {
+            return Ok(Vec::new());
+        };
+        self.tables.write().unwrap().insert(table.to_string(), Arc::new(tbl));
+        Ok(days)
+    }
+
+    /// Stores a record batch as a partition, writing it to disk immediately.
+    /// `mode` decides what happens to existing data for the same table+date.
+    /// The first batch defines the table schema; subsequent batches must have matching
+    /// fields or the call returns an error.
+    ///
+    /// Rows must already be grouped by key with timestamps sorted within each
+    /// key, as an ordered feed produces them. The batch is only checked for
+    /// this, in one pass, and written as is: it is never sorted or copied,
+    /// except to merge with existing rows under [`WriteMode::Append`].
+    pub fn ingest(
+        &self,
+        table: &str,
+        day: EpochDay,
+        batch: RecordBatch,
+        mode: WriteMode,
+    ) -> Result<WriteReport, Error> {
+        Ok(self.ingest_days(table, vec![(day, batch)], mode)?.remove(0))
+    }
+
+    /// Like [`Db::ingest`] for several days of `table` at once, all or
+    /// nothing: if the call fails, or the process crashes before it returns,
+    /// no partition is changed, or every one is once the `Db` is next opened.
+    /// Returns a report per batch.
+    pub fn ingest_days(
+        &self,
+        table: &str,
+        batches: Vec<(EpochDay, RecordBatch)>,
+        mode: WriteMode,
+    ) -> Result<Vec<WriteReport>, Error> {
+        self.ingest_days_with(table, batches, mode, self.options.durability)
+    }
+
+    /// Like [`Db::ingest_days`], but syncing the partitions as `durability`
+    /// says rather than as [`DbOptions::durability`] does, so that each write
+    /// can choose between latency and surviving a crash.
+    pub fn ingest_days_with(
+        &self,
+        table: &str,
+        batches: Vec<(EpochDay, RecordBatch)>,
+        mode: WriteMode,
+        durability: Durability,
+    ) -> Result<Vec<WriteReport>, Error> {
+        if self.options.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Self::check_table_name(table)?;
+        let Some((_, first)) = batches.first() else {
+            return Ok(Vec::new());
+        };
+        let writer = self.writer(table);
+        let _writer = writer.lock().unwrap();
+        let current = self.tables.read().unwrap().get(table).cloned();
+        let mut tbl = match current {
+            Some(tbl) => Table::clone(&tbl),
+            None => Table::new(first.schema())?,
+        };
+
+        let mut reports = Vec::with_capacity(batches.len());
+        let mut days = Vec::with_capacity(batches.len());
+        // Merged here first, so a day given several times is written once.
+        let mut written: BTreeMap<EpochDay, Partition> = BTreeMap::new();
+        for (day, batch) in batches {
+            tbl.check_schema(&batch.schema())?;
+            let mut partition = Partition::new(batch)?;
+            let existing = written.get(&day).or(tbl.partitions.get(&day).map(Arc::as_ref));
+            reports.push(WriteReport {
+                rows: partition.batch.num_rows(),
+                symbols: partition.symbol_index.len(),
+                bytes: 0,
+                created: existing.is_none(),
+                merged: existing.is_some() && mode == WriteMode::Append,
+            });
+            match (mode, existing) {
+                (WriteMode::ErrorIfExists, Some(_)) => {
+                    return Err(Error::PartitionExists(table.to_string(), day));
+                }
+                (WriteMode::Append, Some(existing)) => partition = existing.append(&partition)?,
+                _ => {}
+            }
+            written.insert(day, partition);
+            days.push(day);
+        }
+
+        let dir = self.root.as_ref().map(|root| root.join(table));
+        let mut staged = Vec::with_capacity(written.len());
+        for (day, mut partition) in written {
+            if let Some(dir) = &dir {
+                let path = dir.join(day_to_filename(day));
+                staged.push((partition.stage(&path, durability)?, path));
+            } else {
+                partition.bytes = partition.batch.get_array_memory_size() as u64;
+            }
+            tbl.partitions.insert(day, Arc::new(partition));
+        }
+        for (report, day) in reports.iter_mut().zip(days) {
+            report.bytes = tbl.partitions[&day].bytes;
+        }
+        self.check_quota(table, &tbl)?;
+        if let Some(dir) = &dir {
+            commit(dir, staged, durability)?;
+        }
+        self.tables.write().unwrap().insert(table.to_string(), Arc::new(tbl));
+        Ok(reports)
+    }
+
+    /// Adds the partition files built out of band in `dir`, named
+    /// `<YYYY-MM-DD>.arrow` as within a table directory, to `table` as they
+    /// are — a faster bulk load than [`Db::ingest`], which rewrites its input.
+    /// Each file must hold a single record batch that [`Db::ingest`] would
+    /// accept; all are checked before any is added, and they land all or
+    /// nothing, as with [`Db::ingest_days`]. Existing partitions for the same
+    /// days are replaced.
+    ///
+    /// The files are hard-linked into the table, or copied if on another
+    /// filesystem, so `dir` is left as it was; it must not be modified in
+    /// place afterwards.
+    pub fn ingest_external(&self, table: &str, dir: impl AsRef<Path>) -> Result<Vec<WriteReport>, Error> {
+        if self.options.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Self::check_table_name(table)?;
+        let mut files: Vec<_> = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        files.retain(|e| e.path().extension().is_some_and(|ext| ext == "arrow"));
+        files.sort_by_key(|e| e.file_name());
+
+        let writer = self.writer(table);
+        let _writer = writer.lock().unwrap();
+        let current = self.tables.read().unwrap().get(table).cloned();
+        let mut tbl = current.map(|tbl| Table::clone(&tbl));
+
+        let mut checked = Vec::with_capacity(files.len());
+        let mut reports = Vec::with_capacity(files.len());
+        for entry in files {
+            let path = entry.path();
+            let day = partition_day(&path)?;
+            let mut batches = FileReader::try_new(File::open(&path)?, None)?.collect::<Result<Vec<_>, _>>()?;
+            if batches.len() != 1 {
+                return Err(arrow::error::ArrowError::IpcError(format!(
+                    "expected exactly one record batch, got {}",
+                    batches.len()
+                ))
+                .into());
+            }
+            let batch = batches.pop().unwrap();
+            let tbl = match &mut tbl {
+                Some(tbl) => tbl,
+                None => tbl.insert(Table::new(batch.schema())?),
+            };
+            tbl.check_schema(&batch.schema())?;
+            let mut partition = Partition::new(batch)?;
+            partition.bytes = match &self.root {
+                Some(_) => entry.metadata()?.len(),
+                None => partition.batch.get_array_memory_size() as u64,
+            };
+            reports.push(WriteReport {
+                rows: partition.batch.num_rows(),
+                symbols: partition.symbol_index.len(),
+                bytes: partition.bytes,
+                created: !tbl.partitions.contains_key(&day),
+                merged: false,
+            });
+            tbl.partitions.insert(day, Arc::new(partition));
+            checked.push((day, path));
+        }
+        let Some(mut tbl) = tbl else {
+            return Ok(Vec::new());
+        };
+        self.check_quota(table, &tbl)?;
+        // An in-memory `Db` keeps the copies read to check the files.
+        let Some(root) = &self.root else {
+            self.tables.write().unwrap().insert(table.to_string(), Arc::new(tbl));
+            return Ok(reports);
+        };
+
+        let table_dir = root.join(table);
+        fs::create_dir_all(&table_dir)?;
+        let mut staged = Vec::with_capacity(checked.len());
+        for (day, path) in &checked {
+            let dest = table_dir.join(day_to_filename(*day));
+            let tmp = staging_file(&dest)?.into_temp_path();
+            // The empty staging file only picks a free name to link to.
+            if fs::remove_file(&tmp).and_then(|()| fs::hard_link(path, &tmp)).is_err() {
+                fs::copy(path, &tmp)?;
+            }
+            if self.options.durability == Durability::Always {
+                File::open(&tmp)?.sync_all()?;
+            }
+            staged.push((tmp, dest));
+        }
+        commit(&table_dir, staged, self.options.durability)?;
+
+        // Map the files in place, as `Db::open` would, rather than keep the
+        // copies read to check them.
+        for (day, _) in checked {
+            let path = table_dir.join(day_to_filename(day));
+            tbl.partitions.insert(day, Arc::new(Partition::load(&path, self.options.mmap)?));
+        }
+        self.tables.write().unwrap().insert(table.to_string(), Arc::new(tbl));
+        Ok(reports)
+    }
+
+    /// Fails if `tbl`, the new state of `table`, exceeds the table quota.
+    fn check_quota(&self, table: &str, tbl: &Table) -> Result<(), Error> {
+        if let Some(limit) = self.options.max_table_bytes {
+            let bytes = tbl.partitions.values().map(|part| part.bytes).sum();
+            if bytes > limit {
+                return Err(Error::QuotaExceeded { table: table.to_string(), bytes, limit });
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `src` to the new table `dst`, e.g. to try out changes on a copy
+    /// before touching production data. Partition files are hard-linked where
+    /// possible, which is safe since writes replace files rather than modify
+    /// them; the copy then takes no extra space until either table is written.
+    pub fn copy_table(&self, src: &str, dst: &str) -> Result<(), Error> {
+        if self.options.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Self::check_table_name(src)?;
+        Self::check_table_name(dst)?;
+        if src == dst {
+            return Err(Error::TableExists(dst.to_string()));
+        }
+        // Taken in name order, so two copies between the same tables in
+        // opposite directions can't deadlock.
+        let mut writers = [self.writer(src), self.writer(dst)];
+        if dst < src {
+            writers.reverse();
+        }
+        let _writers = writers.each_ref().map(|writer| writer.lock().unwrap());
+        let table = self.table(src)?;
+        let dst_dir = self.root.as_ref().map(|root| root.join(dst));
+        if self.tables.read().unwrap().contains_key(dst) || dst_dir.as_ref().is_some_and(|dir| dir.exists()) {
+            return Err(Error::TableExists(dst.to_string()));
+        }
+
+        if let (Some(root), Some(dst_dir)) = (&self.root, dst_dir) {
+            // Staged under a hidden name and renamed into place, so a crash
+            // midway leaves no partial `dst`.
+            let staging = tempfile::Builder::new().prefix(&format!(".{dst}.")).tempdir_in(root)?;
+            for &day in table.partitions.keys() {
+                let name = day_to_filename(day);
+                let (from, to) = (root.join(src).join(&name), staging.path().join(&name));
+                if fs::hard_link(&from, &to).is_err() {
+                    fs::copy(&from, &to)?;
+                    if self.options.durability == Durability::Always {
+                        File::open(&to)?.sync_all()?;
+                    }
+                }
+            }
+            sync_dir(staging.path(), self.options.durability)?;
+            fs::rename(staging.keep(), &dst_dir)?;
+            sync_dir(root, self.options.durability)?;
+        }
+
+        self.tables.write().unwrap().insert(dst.to_string(), Arc::new(Table::clone(&table)));
+        Ok(())
+    }
+
+    /// Deletes `table` and its partitions. Queries already holding a snapshot
+    /// of the table finish against it.
+    pub fn drop_table(&self, table: &str) -> Result<(), Error> {
+        if self.options.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Self::check_table_name(table)?;
+        let writer = self.writer(table);
+        let _writer = writer.lock().unwrap();
+        self.table(table)?;
+
+        // Renamed out of sight first, so a crash midway leaves no partial
+        // table; the hidden directory is skipped, and deleted, on open.
+        let trash = match &self.root {
+            Some(root) => {
+                let trash = tempfile::Builder::new().prefix(&format!(".{table}.")).tempdir_in(root)?;
+                fs::rename(root.join(table), trash.path().join(table))?;
+                sync_dir(root, self.options.durability)?;
+                Some(trash)
+            }
+            None => None,
+        };
+        self.tables.write().unwrap().remove(table);
+        if let Some(trash) = trash {
+            trash.close()?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the partitions of `table` for days before `day`, e.g. to
+    /// enforce a retention period, and returns their days. A crash midway
+    /// leaves some of them deleted; calling this again finishes the job.
+    pub fn drop_partitions_before(&self, table: &str, day: EpochDay) -> Result<Vec<EpochDay>, Error> {
+        self.remove_partitions(table, |tbl| tbl.partitions.range(..day).map(|(&day, _)| day).collect())
+    }
+
+    /// Deletes the partitions of `table` for `days`, e.g. on a replica those
+    /// its primary deleted, and returns the days that had one. A crash midway
+    /// leaves some of them deleted, as for [`Db::drop_partitions_before`].
+    pub fn drop_partitions(&self, table: &str, days: &[EpochDay]) -> Result<Vec<EpochDay>, Error> {
+        self.remove_partitions(table, |tbl| days.iter().copied().filter(|day| tbl.partitions.contains_key(day)).collect())
+    }
+
+    /// Deletes the partitions of `table` for the days `select` picks from
+    /// it, and returns those days.
+    fn remove_partitions(&self, table: &str, select: impl FnOnce(&Table) -> Vec<EpochDay>) -> Result<Vec<EpochDay>, Error> {
+        if self.options.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Self::check_table_name(table)?;
+        let writer = self.writer(table);
+        let _writer = writer.lock().unwrap();
+        let mut tbl = Table::clone(&*self.table(table)?);
+        let days = select(&tbl);
+        if days.is_empty() {
+            return Ok(days);
+        }
+        // Queries stop seeing the days before their files go, so none finds
+        // a partition whose file is missing.
+        for day in &days {
+            tbl.partitions.remove(day);
+        }
+        self.tables.write().unwrap().insert(table.to_string(), Arc::new(tbl));
+        if let Some(root) = &self.root {
+            let dir = root.join(table);
+            for &day in &days {
+                let path = dir.join(day_to_filename(day));
+                fs::remove_file(&path)?;
+                match fs::remove_file(checksum_path(&path)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            sync_dir(&dir, self.options.durability)?;
+        }
+        Ok(days)
+    }
+
+    /// Writes the partition of `table` for `day` into the directory `dest` as
+    /// a self-contained Arrow IPC file, schema included, named as within a
+    /// table directory. Returns the file's path, for [`Db::import_partition`]
+    /// on another database.
+    pub fn export_partition(&self, table: &str, day: EpochDay, dest: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let table_ref = self.table(table)?;
+        let partition = table_ref.partition(table, day)?;
+        let path = dest.as_ref().join(day_to_filename(day));
+        fs::create_dir_all(dest.as_ref())?;
+        partition.write(&mut File::create(&path)?)?;
+        Ok(path)
+    }
+
+    /// Ingests a file written by [`Db::export_partition`] into `table`, for
+    /// the day its name gives, subject to the same schema checks as
+    /// [`Db::ingest`].
+    pub fn import_partition(&self, table: &str, path: impl AsRef<Path>, mode: WriteMode) -> Result<WriteReport, Error> {
+        let path = path.as_ref();
+        let day = partition_day(path)?;
+        let reader = FileReader::try_new(File::open(path)?, None)?;
+        let schema = reader.schema();
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        let batch = concat_batches(&schema, &batches)?;
+        self.ingest(table, day, batch, mode)
     }
 
     /// For each query timestamp, finds the matching row in `table` for `symbol`
@@ -386,10 +2431,412 @@ impl Db {
         timestamps: &RecordBatch,
         direction: Direction,
     ) -> Result<RecordBatch, Error> {
-        let table = self
+        let options = AsofOptions { lookback: self.options.lookback, ..AsofOptions::new(direction) };
+        self.join_asof_with(table, symbol, timestamps, &options)
+    }
+
+    /// Like [`Db::join_asof`], with additional [`AsofOptions`].
+    pub fn join_asof_with(
+        &self,
+        table: &str,
+        symbol: &str,
+        timestamps: &RecordBatch,
+        options: &AsofOptions,
+    ) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.join_asof(symbol, timestamps, options, &mut AsofStats::default())?)
+    }
+
+    /// Like [`Db::join_asof_with`], but returns only the timestamp of each
+    /// probe's match (null if none), without reading any value columns beyond
+    /// those `options.filter` needs — a cheap check for whether and when data
+    /// exists. `options.interpolate` has no effect.
+    pub fn asof_timestamps(
+        &self,
+        table: &str,
+        symbol: &str,
+        timestamps: &RecordBatch,
+        options: &AsofOptions,
+    ) -> Result<Int64Array, Error> {
+        Ok(self.table(table)?.asof_timestamps(symbol, timestamps, options)?)
+    }
+
+    /// Like [`Db::join_asof_with`] for the single primitive `column`, writing
+    /// each probe's matched value (or `None`) into the caller's `out` buffer
+    /// instead of allocating a `RecordBatch` — for callers issuing many small
+    /// probe batches. `T` must match the column's type, e.g. `Float64Type`, or
+    /// `Int64Type` for `timestamp`. `options.interpolate` has no effect.
+    pub fn join_asof_into<T: ArrowPrimitiveType>(
+        &self,
+        table: &str,
+        symbol: &str,
+        timestamps: &[i64],
+        column: &str,
+        options: &AsofOptions,
+        out: &mut [Option<T::Native>],
+    ) -> Result<(), Error> {
+        Ok(self.table(table)?.join_asof_into::<T>(symbol, timestamps, column, options, out)?)
+    }
+
+    /// Like [`Db::join_asof_with`], also returning [`AsofStats`] for the join.
+    pub fn join_asof_explain(
+        &self,
+        table: &str,
+        symbol: &str,
+        timestamps: &RecordBatch,
+        options: &AsofOptions,
+    ) -> Result<(RecordBatch, AsofStats), Error> {
+        let mut stats = AsofStats::default();
+        let batch = self.table(table)?.join_asof(symbol, timestamps, options, &mut stats)?;
+        Ok((batch, stats))
+    }
+
+    /// For each probe row, finds the matching row in `table` with the same key
+    /// using an as-of join.
+    ///
+    /// `probes` holds a `timestamp` column and a Utf8 column for each of the
+    /// table's key columns — `symbol` plus any declared in [`KEYS_METADATA`].
+    /// Probes may mix keys freely; a probe with a null key never matches.
+    /// Returns the same nullable columns as [`Db::join_asof`], in probe order.
+    pub fn join_asof_keyed(
+        &self,
+        table: &str,
+        probes: &RecordBatch,
+        options: &AsofOptions,
+    ) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.join_asof_keyed(probes, options)?)
+    }
+
+    /// As-of joins `right` onto every row of `left` with a timestamp in `range`,
+    /// matching on the tables' key columns, which must be the same.
+    ///
+    /// Each left key's rows are already sorted, so they are matched with a merge
+    /// walk over the right rows rather than a binary search per row. Left rows
+    /// are returned grouped by key (sorted) then in time order; see [`TableJoin`].
+    pub fn asof_tables(
+        &self,
+        left: &str,
+        right: &str,
+        range: Range<i64>,
+        options: &AsofOptions,
+    ) -> Result<TableJoin, Error> {
+        let left = self.table(left)?;
+        let right = self.table(right)?;
+        if key_columns(&left.schema) != key_columns(&right.schema) {
+            return Err(arrow::error::ArrowError::SchemaError(format!(
+                "key columns differ: {:?} vs {:?}",
+                key_columns(&left.schema),
+                key_columns(&right.schema),
+            ))
+            .into());
+        }
+
+        let keys: BTreeSet<&str> = left
+            .partitions_in(range.clone())
+            .flat_map(|(_, part)| part.symbol_index.keys().map(String::as_str))
+            .collect();
+
+        let mut key_col: Vec<&str> = Vec::new();
+        let mut left_rows: Vec<Option<(EpochDay, usize)>> = Vec::new();
+        let mut right_rows: Vec<Option<(EpochDay, usize)>> = Vec::new();
+        let mut opposite: Vec<Option<(EpochDay, usize)>> = Vec::new();
+        let mut left_ts: Vec<i64> = Vec::new();
+        for key in keys {
+            let mut probe_ts: Vec<i64> = Vec::new();
+            for (day, part, rows) in left.scan(key, range.clone()) {
+                probe_ts.extend_from_slice(&part.timestamps()[rows.clone()]);
+                left_rows.extend(rows.map(|i| Some((day, i))));
+            }
+            key_col.extend(std::iter::repeat_n(key, probe_ts.len()));
+            right_rows.extend(right.locate_asof(key, &probe_ts, options, Search::Linear, &mut AsofStats::default())?);
+            opposite.extend(right.locate_opposite(key, &probe_ts, options, Search::Linear)?);
+            left_ts.extend(probe_ts);
+        }
+
+        let left_schema = output_schema(&left.schema);
+        let mut fields = vec![Arc::new(Field::new(SYMBOL_COL, DataType::Utf8, false))];
+        fields.extend(left_schema.fields().iter().cloned());
+        let mut left_columns: Vec<ArrayRef> = vec![Arc::new(StringArray::from(key_col))];
+        left_columns.extend(left.gather(&left_schema, &left_rows)?);
+
+        let right_schema = output_schema(&right.schema);
+        let right_columns = right.asof_columns(&right_schema, &left_ts, &right_rows, &opposite, options)?;
+        Ok(TableJoin {
+            left: RecordBatch::try_new(Arc::new(Schema::new(fields)), left_columns)?,
+            right: RecordBatch::try_new(right_schema, right_columns)?,
+        })
+    }
+
+    /// For each query timestamp `t`, returns every row in `table` for `symbol`
+    /// with a timestamp in the trailing window `[t - window_us, t]`.
+    ///
+    /// Matches for all probes share one `RecordBatch`; see [`WindowJoin`].
+    pub fn join_window(
+        &self,
+        table: &str,
+        symbol: &str,
+        timestamps: &RecordBatch,
+        window_us: i64,
+    ) -> Result<WindowJoin, Error> {
+        if window_us < 0 {
+            return Err(Error::InvalidInterval(window_us));
+        }
+        Ok(self.table(table)?.join_window(symbol, timestamps, window_us)?)
+    }
+
+    /// For each query timestamp, returns up to `n` of the most recent rows in
+    /// `table` for `symbol` at or before it — a backward as-of join that keeps
+    /// the last `n` matches instead of one.
+    ///
+    /// Each probe's rows are in time order; see [`WindowJoin`].
+    pub fn join_last_n(
+        &self,
+        table: &str,
+        symbol: &str,
+        timestamps: &RecordBatch,
+        n: usize,
+    ) -> Result<WindowJoin, Error> {
+        Ok(self.table(table)?.join_last_n(symbol, timestamps, n)?)
+    }
+
+    /// As-of joins `column` for each of `symbols` at every probe timestamp,
+    /// producing a dense panel: a `timestamp` column followed by one nullable
+    /// column per symbol, named after it — e.g. a price matrix ready for
+    /// Polars or ndarray.
+    pub fn pivot(
+        &self,
+        table: &str,
+        symbols: &[&str],
+        timestamps: &RecordBatch,
+        column: &str,
+        options: &AsofOptions,
+    ) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.pivot(symbols, timestamps, column, options)?)
+    }
+
+    /// As-of joins each of `symbols` in `table` at the single timestamp `ts` —
+    /// e.g. marking every symbol at 12:00. Cheaper than [`Db::join_asof_keyed`]
+    /// with a constant timestamp, as the probe's partition is resolved once.
+    ///
+    /// The result has a leading Utf8 `symbol` column, in the order given,
+    /// followed by the same nullable columns as [`Db::join_asof`].
+    pub fn join_asof_broadcast(
+        &self,
+        table: &str,
+        symbols: &[&str],
+        ts: i64,
+        options: &AsofOptions,
+    ) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.join_asof_broadcast(symbols, ts, options)?)
+    }
+
+    /// Returns the backward as-of row at `ts` for every symbol in `table`,
+    /// without the caller enumerating symbols — e.g. end-of-day marks.
+    ///
+    /// The symbol universe is taken from the latest partition on or before
+    /// `ts`'s day. The result has a leading Utf8 `symbol` column, sorted, followed
+    /// by the same nullable columns as [`Db::join_asof`].
+    pub fn snapshot_at(&self, table: &str, ts: i64) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.snapshot_at(ts, self.options.lookback)?)
+    }
+
+    /// Returns the number of rows in `table` for `symbol` with a timestamp in
+    /// `range`, using only the symbol index and timestamp column.
+    pub fn count(&self, table: &str, symbol: &str, range: Range<i64>) -> Result<usize, Error> {
+        Ok(self.table(table)?.scan(symbol, range).map(|(_, _, rows)| rows.len()).sum())
+    }
+
+    /// Returns the first `n` rows in `table` for `symbol` with a timestamp in
+    /// `range`, in time order, with the same columns as [`Db::join_asof`].
+    pub fn head(&self, table: &str, symbol: &str, range: Range<i64>, n: usize) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.head_tail(symbol, range, n, false)?)
+    }
+
+    /// Like [`Db::head`], but returns the last `n` rows.
+    pub fn tail(&self, table: &str, symbol: &str, range: Range<i64>, n: usize) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.head_tail(symbol, range, n, true)?)
+    }
+
+    /// Returns the rows in `table` for `symbol` with a timestamp in `range`, in
+    /// time order, with the same columns as [`Db::join_asof`] — or only
+    /// `columns`, in the order given.
+    pub fn range(
+        &self,
+        table: &str,
+        symbol: &str,
+        range: Range<i64>,
+        columns: Option<&[&str]>,
+    ) -> Result<RecordBatch, Error> {
+        let table = self.table(table)?;
+        let mut schema = output_schema(&table.schema);
+        if let Some(columns) = columns {
+            let indices = columns.iter().map(|name| schema.index_of(name)).collect::<Result<Vec<_>, _>>()?;
+            schema = Arc::new(schema.project(&indices)?);
+        }
+        Ok(table.range(symbol, range, &schema)?)
+    }
+
+    /// Returns a thinned-out view of `symbol`'s rows in `range` — every nth row,
+    /// or one row per time bucket — for plotting or eyeballing large tables.
+    /// Rows are in time order, with the same columns as [`Db::join_asof`].
+    pub fn sample(&self, table: &str, symbol: &str, range: Range<i64>, stride: Stride) -> Result<RecordBatch, Error> {
+        match stride {
+            Stride::Rows(0) => return Err(Error::InvalidInterval(0)),
+            Stride::Interval(interval_us) if interval_us <= 0 => return Err(Error::InvalidInterval(interval_us)),
+            _ => {}
+        }
+        Ok(self.table(table)?.sample(symbol, range, stride)?)
+    }
+
+    /// Streams the rows of `symbols` in `table` with timestamps in `range` as a
+    /// single time-ordered sequence, hiding partition boundaries.
+    ///
+    /// Yields batches of at most 65,536 rows, none spanning two partitions,
+    /// each with a leading Utf8 `symbol` column followed by the same nullable
+    /// columns as [`Db::join_asof`]. Rows with equal timestamps keep the order of `symbols`.
+    pub fn scan<'a>(
+        &'a self,
+        table: &str,
+        symbols: &'a [&'a str],
+        range: Range<i64>,
+    ) -> Result<impl Iterator<Item = Result<RecordBatch, Error>> + 'a, Error> {
+        Ok(self.table(table)?.merge_scan(Some(symbols), range).map(|batch| Ok(batch?)))
+    }
+
+    /// Like [`Db::scan`] over every key in `table`, e.g. to replay the whole
+    /// market in time order. Rows with equal timestamps are ordered by key.
+    ///
+    /// Partitions stay symbol-major, which as-of joins rely on, so each one is
+    /// merged by timestamp as it is reached: each row costs a heap step,
+    /// O(log k) over the partition's k keys, and a batch gathers its rows
+    /// from up to k places in the file rather than reading it in order.
+    pub fn replay(
+        &self,
+        table: &str,
+        range: Range<i64>,
+    ) -> Result<impl Iterator<Item = Result<RecordBatch, Error>>, Error> {
+        Ok(self.table(table)?.merge_scan(None, range).map(|batch| Ok(batch?)))
+    }
+
+    /// Returns the sorted, distinct keys with rows in `table` across the
+    /// partitions for `days`, read from the symbol indexes alone. For tables
+    /// with extra key columns, each key's values are joined by [`KEY_SEPARATOR`].
+    pub fn symbols(&self, table: &str, days: impl RangeBounds<EpochDay>) -> Result<Vec<String>, Error> {
+        let table = self.table(table)?;
+        let symbols: BTreeSet<&String> = table
+            .partitions
+            .range(days)
+            .flat_map(|(_, part)| part.symbol_index.keys())
+            .collect();
+        Ok(symbols.into_iter().cloned().collect())
+    }
+
+    /// The schema of `table`, which ingested batches must match.
+    pub fn schema(&self, table: &str) -> Result<SchemaRef, Error> {
+        Ok(self.table(table)?.schema.clone())
+    }
+
+    /// Describes every table, in order of name.
+    pub fn tables(&self) -> Vec<TableInfo> {
+        let mut tables: Vec<TableInfo> = self
             .tables
-            .get(table)
-            .ok_or_else(|| Error::TableNotFound(table.to_string()))?;
-        Ok(table.join_asof(symbol, timestamps, direction)?)
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, table)| TableInfo {
+                name: name.clone(),
+                schema: table.schema.clone(),
+                days: table
+                    .partitions
+                    .first_key_value()
+                    .zip(table.partitions.last_key_value())
+                    .map(|((&first, _), (&last, _))| first..=last),
+            })
+            .collect();
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        tables
+    }
+
+    /// Describes every partition, in order of table and day.
+    pub fn partitions(&self) -> Result<Vec<PartitionInfo>, Error> {
+        let tables: BTreeMap<String, Arc<Table>> =
+            self.tables.read().unwrap().iter().map(|(name, table)| (name.clone(), table.clone())).collect();
+        let mut partitions = Vec::new();
+        for (name, table) in tables {
+            for (&day, partition) in &table.partitions {
+                partitions.push(PartitionInfo {
+                    table: name.clone(),
+                    day,
+                    rows: partition.batch.num_rows(),
+                    bytes: partition.bytes,
+                    checksum: partition.checksum()?,
+                });
+            }
+        }
+        Ok(partitions)
+    }
+
+    /// The rows of the partition of `table` for `day`, as stored.
+    pub fn partition(&self, table: &str, day: EpochDay) -> Result<RecordBatch, Error> {
+        Ok(self.table(table)?.partition(table, day)?.batch.clone())
+    }
+
+    /// Reads the bytes in `range` of the file of the partition of `table` for
+    /// `day`, cut short at its end, e.g. to copy the file a chunk at a time.
+    /// Fails with [`Error::PartitionChanged`] unless the partition's checksum
+    /// is `checksum`; a write that replaces the file between chunks still
+    /// mixes versions, which the copy's checksum then shows.
+    pub fn read_partition_file(&self, table: &str, day: EpochDay, checksum: u32, range: Range<u64>) -> Result<Vec<u8>, Error> {
+        let tbl = self.table(table)?;
+        let partition = tbl.partition(table, day)?;
+        if partition.checksum()? != checksum {
+            return Err(Error::PartitionChanged(table.to_string(), day));
+        }
+        let slice = |bytes: &[u8]| {
+            let end = (range.end as usize).min(bytes.len());
+            bytes[(range.start as usize).min(end)..end].to_vec()
+        };
+        match (&partition.file, &self.root) {
+            (Some(file), _) => Ok(slice(file.as_slice())),
+            (None, Some(root)) => {
+                let mut file = File::open(root.join(table).join(day_to_filename(day)))?;
+                file.seek(SeekFrom::Start(range.start))?;
+                let mut bytes = Vec::new();
+                file.take(range.end.saturating_sub(range.start)).read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }
+            (None, None) => {
+                let mut bytes = Vec::new();
+                partition.write(&mut bytes)?;
+                Ok(slice(&bytes))
+            }
+        }
+    }
+
+    /// Returns an error unless `name` is usable as a table name: non-empty,
+    /// without path separators, and not starting with `.`, which would
+    /// address files outside the table's own directory or hidden ones in it.
+    pub fn check_table_name(name: &str) -> Result<(), Error> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(Error::InvalidTableName(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// A snapshot of the table `name`, unaffected by later writes.
+    fn table(&self, name: &str) -> Result<Arc<Table>, Error> {
+        self.tables
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::TableNotFound(name.to_string()))
+    }
+
+    /// The lock serializing writes to the table `name`. Entries outlive
+    /// dropped tables, so a writer waiting on one never races a writer that
+    /// found a fresh lock for a recreated table.
+    fn writer(&self, name: &str) -> Arc<Mutex<()>> {
+        let mut writers = self.writers.lock().unwrap();
+        Arc::clone(writers.entry(name.to_string()).or_default())
     }
 }
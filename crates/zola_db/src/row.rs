@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use arrow::array::types::{Float64Type, Int32Type, Int64Type};
+use arrow::array::{
+    Array, ArrayRef, ArrowPrimitiveType, AsArray, BooleanArray, Int32Array, PrimitiveArray,
+    RunArray, StringArray,
+};
+use arrow::datatypes::{DataType, Field};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+
+use crate::{SYMBOL_COL, TIMESTAMP_COL};
+
+/// A struct mapped to table rows, usually via `#[derive(ZolaRow)]`.
+///
+/// The derive takes one `String` field marked `#[symbol]`, one `i64` field
+/// marked `#[timestamp]`, and any number of value fields whose types implement
+/// [`RowValue`]; each value column is named after its field.
+///
+/// ```ignore
+/// #[derive(ZolaRow)]
+/// struct Trade {
+///     #[symbol]
+///     symbol: String,
+///     #[timestamp]
+///     ts: i64,
+///     price: f64,
+///     size: Option<f64>,
+/// }
+/// ```
+pub trait ZolaRow: Sized {
+    /// The table schema: the run-end encoded `symbol` column, `timestamp`, then
+    /// one column per value field.
+    fn schema() -> arrow::datatypes::SchemaRef;
+
+    /// Builds a batch for [`crate::Db::ingest`] from `rows`, which must be
+    /// grouped by symbol and in time order within each symbol.
+    fn to_batch(rows: &[Self]) -> Result<RecordBatch, ArrowError>;
+
+    /// Decodes a query result, such as that of [`crate::Db::join_asof`], into
+    /// one entry per row — `None` where the timestamp is null, i.e. a probe
+    /// without a match. Results without a `symbol` column leave the symbol
+    /// field empty.
+    fn decode(batch: &RecordBatch) -> Result<Vec<Option<Self>>, ArrowError>;
+}
+
+/// Typed access to query results, e.g. `batch.decode::<Trade>()`.
+pub trait DecodeRows {
+    /// Decodes each row as `T`, with `None` for probes without a match; see
+    /// [`ZolaRow::decode`].
+    fn decode<T: ZolaRow>(&self) -> Result<Vec<Option<T>>, ArrowError>;
+
+    /// Decodes the column `name` as `T`, with `None` for nulls. Untyped access
+    /// is [`RecordBatch::column_by_name`].
+    fn decode_column<T: RowValue>(&self, name: &str) -> Result<Vec<Option<T>>, ArrowError>;
+
+    /// Iterates over the rows — one per probe for as-of results — without
+    /// decoding them up front.
+    fn rows(&self) -> impl ExactSizeIterator<Item = RowView<'_>>;
+
+    /// Whether row `i` is null — for as-of results, whether probe `i` had no
+    /// match. Nulls are tracked by Arrow's validity bitmaps, so no value is
+    /// ever a stand-in for null.
+    fn is_null(&self, i: usize) -> bool;
+}
+
+impl DecodeRows for RecordBatch {
+    fn decode<T: ZolaRow>(&self) -> Result<Vec<Option<T>>, ArrowError> {
+        T::decode(self)
+    }
+
+    fn decode_column<T: RowValue>(&self, name: &str) -> Result<Vec<Option<T>>, ArrowError> {
+        let column = __private::column::<T>(self, name)?;
+        Ok((0..self.num_rows()).map(|i| T::read(column, i)).collect())
+    }
+
+    fn rows(&self) -> impl ExactSizeIterator<Item = RowView<'_>> {
+        (0..self.num_rows()).map(|index| RowView { batch: self, index })
+    }
+
+    fn is_null(&self, i: usize) -> bool {
+        self.column_by_name(TIMESTAMP_COL)
+            .is_none_or(|column| column.is_null(i))
+    }
+}
+
+/// One row of a query result, from [`DecodeRows::rows`].
+#[derive(Debug, Clone, Copy)]
+pub struct RowView<'a> {
+    batch: &'a RecordBatch,
+    index: usize,
+}
+
+impl RowView<'_> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// See [`DecodeRows::is_null`].
+    pub fn is_null(&self) -> bool {
+        self.batch.is_null(self.index)
+    }
+
+    /// The row's timestamp, or `None` for a probe without a match.
+    pub fn timestamp(&self) -> Option<i64> {
+        let column = self.batch.column_by_name(TIMESTAMP_COL)?;
+        i64::read(column.as_ref(), self.index)
+    }
+
+    /// Reads the column `name` as `T`, with `None` for null.
+    pub fn get<T: RowValue>(&self, name: &str) -> Result<Option<T>, ArrowError> {
+        let column = __private::column::<T>(self.batch, name)?;
+        Ok(T::read(column, self.index))
+    }
+}
+
+/// A Rust type that can be stored in a value column of a [`ZolaRow`].
+/// `Option<T>` maps to a nullable column of `T`.
+pub trait RowValue: Sized {
+    const NULLABLE: bool = false;
+
+    fn data_type() -> DataType;
+
+    fn to_array_opt(values: impl Iterator<Item = Option<Self>>) -> ArrayRef;
+
+    fn to_array(values: impl Iterator<Item = Self>) -> ArrayRef {
+        Self::to_array_opt(values.map(Some))
+    }
+
+    /// Reads row `i` of `array`, which has type [`RowValue::data_type`];
+    /// `None` if it is null.
+    fn read(array: &dyn Array, i: usize) -> Option<Self>;
+}
+
+macro_rules! primitive_row_value {
+    ($native:ty, $arrow:ty) => {
+        impl RowValue for $native {
+            fn data_type() -> DataType {
+                <$arrow>::DATA_TYPE
+            }
+
+            fn to_array_opt(values: impl Iterator<Item = Option<Self>>) -> ArrayRef {
+                Arc::new(PrimitiveArray::<$arrow>::from_iter(values))
+            }
+
+            fn read(array: &dyn Array, i: usize) -> Option<Self> {
+                array
+                    .is_valid(i)
+                    .then(|| array.as_primitive::<$arrow>().value(i))
+            }
+        }
+    };
+}
+
+primitive_row_value!(i32, Int32Type);
+primitive_row_value!(i64, Int64Type);
+primitive_row_value!(f64, Float64Type);
+
+impl RowValue for bool {
+    fn data_type() -> DataType {
+        DataType::Boolean
+    }
+
+    fn to_array_opt(values: impl Iterator<Item = Option<Self>>) -> ArrayRef {
+        Arc::new(BooleanArray::from_iter(values))
+    }
+
+    fn read(array: &dyn Array, i: usize) -> Option<Self> {
+        array.is_valid(i).then(|| array.as_boolean().value(i))
+    }
+}
+
+impl RowValue for String {
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn to_array_opt(values: impl Iterator<Item = Option<Self>>) -> ArrayRef {
+        Arc::new(StringArray::from_iter(values))
+    }
+
+    fn read(array: &dyn Array, i: usize) -> Option<Self> {
+        array
+            .is_valid(i)
+            .then(|| array.as_string::<i32>().value(i).to_string())
+    }
+}
+
+impl<T: RowValue> RowValue for Option<T> {
+    const NULLABLE: bool = true;
+
+    fn data_type() -> DataType {
+        T::data_type()
+    }
+
+    fn to_array_opt(values: impl Iterator<Item = Option<Self>>) -> ArrayRef {
+        T::to_array_opt(values.map(Option::flatten))
+    }
+
+    fn read(array: &dyn Array, i: usize) -> Option<Self> {
+        Some(T::read(array, i))
+    }
+}
+
+/// Run-end encodes `symbols` into the form of a partition's `symbol` column.
+pub fn encode_symbols<'a>(symbols: impl Iterator<Item = &'a str>) -> Result<ArrayRef, ArrowError> {
+    let mut run_ends: Vec<i32> = Vec::new();
+    let mut values: Vec<&str> = Vec::new();
+    for (i, symbol) in symbols.enumerate() {
+        if values.last() == Some(&symbol) {
+            *run_ends.last_mut().unwrap() = i as i32 + 1;
+        } else {
+            run_ends.push(i as i32 + 1);
+            values.push(symbol);
+        }
+    }
+    let run_array =
+        RunArray::<Int32Type>::try_new(&Int32Array::from(run_ends), &StringArray::from(values))?;
+    Ok(Arc::new(run_array))
+}
+
+/// Items used by code generated by `#[derive(ZolaRow)]`.
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+
+    pub use super::encode_symbols;
+    pub use arrow::datatypes::{Schema, SchemaRef};
+    pub use arrow::error::ArrowError;
+    pub use arrow::record_batch::RecordBatch;
+
+    pub fn symbol_field() -> Field {
+        let empty = encode_symbols(std::iter::empty()).unwrap();
+        Field::new(SYMBOL_COL, empty.data_type().clone(), false)
+    }
+
+    pub fn field<T: RowValue>(name: &str) -> Field {
+        Field::new(name, T::data_type(), T::NULLABLE)
+    }
+
+    /// Looks up column `name`, checking that it can be read as `T`.
+    pub fn column<'a, T: RowValue>(
+        batch: &'a RecordBatch,
+        name: &str,
+    ) -> Result<&'a dyn Array, ArrowError> {
+        let column = batch
+            .column_by_name(name)
+            .ok_or_else(|| ArrowError::SchemaError(format!("missing column {name:?}")))?;
+        if column.data_type() != &T::data_type() {
+            return Err(ArrowError::SchemaError(format!(
+                "column {name:?} has type {}, expected {}",
+                column.data_type(),
+                T::data_type()
+            )));
+        }
+        Ok(column.as_ref())
+    }
+
+    pub fn read<T: RowValue>(array: &dyn Array, i: usize, name: &str) -> Result<T, ArrowError> {
+        T::read(array, i).ok_or_else(|| {
+            ArrowError::InvalidArgumentError(format!("unexpected null in column {name:?}"))
+        })
+    }
+
+    /// Returns the batch's symbols, from a Utf8 or run-end encoded `symbol`
+    /// column, if it has one.
+    pub fn symbols(batch: &RecordBatch) -> Result<Option<Vec<String>>, ArrowError> {
+        let Some(column) = batch.column_by_name(SYMBOL_COL) else {
+            return Ok(None);
+        };
+        let symbols = match column.data_type() {
+            DataType::Utf8 => column
+                .as_string::<i32>()
+                .iter()
+                .map(|s| s.unwrap_or_default().to_string())
+                .collect(),
+            DataType::RunEndEncoded(_, _) => {
+                let runs = column.as_run_opt::<Int32Type>().ok_or_else(|| {
+                    ArrowError::SchemaError("symbol run ends must be Int32".into())
+                })?;
+                let values = runs
+                    .values()
+                    .as_string_opt::<i32>()
+                    .ok_or_else(|| ArrowError::SchemaError("symbol values must be Utf8".into()))?;
+                (0..runs.len())
+                    .map(|i| values.value(runs.get_physical_index(i)).to_string())
+                    .collect()
+            }
+            other => {
+                return Err(ArrowError::SchemaError(format!(
+                    "symbol column has unsupported type {other}"
+                )));
+            }
+        };
+        Ok(Some(symbols))
+    }
+}
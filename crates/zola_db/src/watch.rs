@@ -0,0 +1,58 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::{Db, EpochDay, Error};
+
+/// Refreshes a [`Db`] in the background until dropped; see [`Db::watch`].
+pub struct Watcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread and ends its loop.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Db {
+    /// Calls [`Db::refresh`] every `interval` on a background thread, so that
+    /// a read-only query server follows a separate ingest process without
+    /// polling code of its own. `on_refresh` receives each result, e.g. to
+    /// log new partitions or errors.
+    ///
+    /// Stops when the returned [`Watcher`] or the last other reference to the
+    /// `Db` is dropped.
+    ///
+    /// This polls rather than waiting on filesystem events: a refresh only
+    /// lists the table directories, which is cheap at any sensible interval,
+    /// and events aren't delivered for writes by other hosts to the network
+    /// filesystems that a separate ingest process often shares the data
+    /// directory over.
+    pub fn watch(
+        self: &Arc<Self>,
+        interval: Duration,
+        mut on_refresh: impl FnMut(Result<Vec<(String, EpochDay)>, Error>) + Send + 'static,
+    ) -> Watcher {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let db: Weak<Db> = Arc::downgrade(self);
+        let thread = std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(db) = db.upgrade() else {
+                    break;
+                };
+                on_refresh(db.refresh());
+            }
+        });
+        Watcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use arrow::array::{Int64Array, RecordBatch};
+use arrow::datatypes::{DataType, Field, Schema};
+use zola_db::{Db, DecodeRows, Direction, EpochDay, TIMESTAMP_COL, WriteMode, ZolaRow};
+
+#[derive(Debug, Clone, PartialEq, ZolaRow)]
+struct Trade {
+    #[symbol]
+    symbol: String,
+    #[timestamp]
+    ts: i64,
+    price: f64,
+    size: Option<i64>,
+}
+
+fn trade(ts: i64, price: f64, size: Option<i64>) -> Trade {
+    Trade {
+        symbol: "AAPL".to_string(),
+        ts,
+        price,
+        size,
+    }
+}
+
+#[test]
+fn round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Db::open(dir.path()).unwrap();
+    let trades = [
+        trade(10, 1.0, Some(100)),
+        trade(20, 2.0, None),
+        trade(30, 3.0, Some(300)),
+    ];
+    let batch = Trade::to_batch(&trades).unwrap();
+    assert_eq!(batch.schema(), Trade::schema());
+    db.ingest("trades", EpochDay(0), batch, WriteMode::ErrorIfExists)
+        .unwrap();
+
+    let stored = db.partition("trades", EpochDay(0)).unwrap();
+    let stored: Vec<_> = stored.decode::<Trade>().unwrap();
+    assert_eq!(stored, trades.clone().map(Some));
+
+    // The first probe is before any trade, so it has no match.
+    let schema = Schema::new(vec![Field::new(TIMESTAMP_COL, DataType::Int64, false)]);
+    let probes = Int64Array::from(vec![5, 10, 25, 30]);
+    let probes = RecordBatch::try_new(Arc::new(schema), vec![Arc::new(probes)]).unwrap();
+    let joined = db
+        .join_asof("trades", "AAPL", &probes, Direction::Backward)
+        .unwrap();
+    assert!(joined.is_null(0));
+
+    // As-of results have no symbol column, so the symbol is left empty.
+    let unnamed = |t: &Trade| Trade {
+        symbol: String::new(),
+        ..t.clone()
+    };
+    let expected = vec![
+        None,
+        Some(unnamed(&trades[0])),
+        Some(unnamed(&trades[1])),
+        Some(unnamed(&trades[2])),
+    ];
+    assert_eq!(Trade::decode(&joined).unwrap(), expected);
+    assert_eq!(
+        joined.decode_column::<i64>("size").unwrap(),
+        [None, Some(100), None, Some(300)]
+    );
+}
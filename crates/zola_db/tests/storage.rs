@@ -0,0 +1,105 @@
+use std::fs;
+use std::sync::Arc;
+
+use arrow::array::{Array, AsArray, Int32Array, Int64Array, RecordBatch, RunArray, StringArray};
+use arrow::datatypes::{DataType, Field, Int32Type, Int64Type, Schema};
+use zola_db::{Db, DbOptions, EpochDay, SYMBOL_COL, TIMESTAMP_COL, WriteMode};
+
+fn batch(values: &[i64]) -> RecordBatch {
+    let symbols = RunArray::<Int32Type>::try_new(
+        &Int32Array::from(vec![values.len() as i32]),
+        &StringArray::from(vec!["A"]),
+    )
+    .unwrap();
+    let schema = Schema::new(vec![
+        Field::new(SYMBOL_COL, symbols.data_type().clone(), false),
+        Field::new(TIMESTAMP_COL, DataType::Int64, false),
+        Field::new("price", DataType::Int64, false),
+    ]);
+    let timestamps = Int64Array::from_iter_values(0..values.len() as i64);
+    let prices = Int64Array::from(values.to_vec());
+    RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(symbols), Arc::new(timestamps), Arc::new(prices)],
+    )
+    .unwrap()
+}
+
+fn prices(batch: &RecordBatch) -> Vec<i64> {
+    batch
+        .column_by_name("price")
+        .unwrap()
+        .as_primitive::<Int64Type>()
+        .values()
+        .to_vec()
+}
+
+// Windows can't replace a mapped file, so there this goes through moving the
+// old one aside.
+#[test]
+fn overwrite_mapped_day() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = DbOptions {
+        mmap: true,
+        ..DbOptions::default()
+    };
+    let db = Db::open_with_options(dir.path(), options).unwrap();
+    db.ingest(
+        "trades",
+        EpochDay(0),
+        batch(&[1, 2, 3]),
+        WriteMode::ErrorIfExists,
+    )
+    .unwrap();
+    let old = db.partition("trades", EpochDay(0)).unwrap();
+
+    db.ingest("trades", EpochDay(0), batch(&[4, 5]), WriteMode::Overwrite)
+        .unwrap();
+    assert_eq!(
+        prices(&db.partition("trades", EpochDay(0)).unwrap()),
+        [4, 5]
+    );
+    assert_eq!(prices(&old), [1, 2, 3]);
+    drop(old);
+    drop(db);
+
+    let db = Db::open(dir.path()).unwrap();
+    assert_eq!(
+        prices(&db.partition("trades", EpochDay(0)).unwrap()),
+        [4, 5]
+    );
+    let left = fs::read_dir(dir.path().join("trades")).unwrap();
+    assert!(
+        left.map(|entry| entry.unwrap().file_name())
+            .all(|name| !name.to_string_lossy().contains(".old."))
+    );
+}
+
+#[test]
+fn restore_day_moved_aside() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = Db::open(dir.path()).unwrap();
+    db.ingest(
+        "trades",
+        EpochDay(0),
+        batch(&[1, 2, 3]),
+        WriteMode::ErrorIfExists,
+    )
+    .unwrap();
+    drop(db);
+
+    // As if the process crashed while replacing the day on Windows.
+    let table = dir.path().join("trades");
+    fs::rename(
+        table.join("1970-01-01.arrow"),
+        table.join(".1970-01-01.old.x"),
+    )
+    .unwrap();
+
+    let db = Db::open(dir.path()).unwrap();
+    assert_eq!(
+        prices(&db.partition("trades", EpochDay(0)).unwrap()),
+        [1, 2, 3]
+    );
+    assert!(!table.join(".1970-01-01.old.x").exists());
+}
@@ -1,14 +1,150 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use arrow::datatypes::SchemaRef;
 use arrow::record_batch::RecordBatch;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use zola_db_proto::{Request, Response};
 
-pub use zola_db_proto::{Direction, Market};
+pub use zola_db_proto::{
+    Compression, Direction, Durability, ErrorCode, MAX_WRITE_CHUNKS, MAX_WRITE_TOKEN_LEN, Market,
+    PartitionInfo, TableInfo, WriteMode,
+};
+
+pub use tls::Tls;
+
+mod tls;
+
+/// One as-of join of [`Client::asof_batch`], with the arguments of
+/// [`Client::join_asof`].
+#[derive(Debug, Clone)]
+pub struct AsofRequest {
+    pub table: String,
+    pub symbol: String,
+    pub timestamps: RecordBatch,
+    pub direction: Direction,
+}
+
+/// The answer to [`Client::ping`].
+#[derive(Debug, Clone)]
+pub struct Pong {
+    /// The version of the server.
+    pub version: String,
+    /// The time from sending the ping to its answer.
+    pub latency: Duration,
+}
+
+/// Options for [`Client::write_with`].
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    pub mode: WriteMode,
+    /// [`Durability::Never`] returns as soon as the server has written the
+    /// rows, which a crash of the server can then lose.
+    pub durability: Durability,
+    /// Identifies the write, so that sending it again after a timeout or
+    /// reconnect doesn't apply it twice if the first attempt went through.
+    /// Must be unique among writes to the table and day, and at most
+    /// [`MAX_WRITE_TOKEN_LEN`] bytes; the server remembers only recent
+    /// tokens, and none across a restart.
+    pub token: Option<String>,
+    /// Sends the rows this many at a time, each chunk encoded only as it is
+    /// sent, so that a large write needs no encoded copy of itself in the
+    /// client's memory nor one frame too large to send. At most
+    /// [`MAX_WRITE_CHUNKS`] chunks are allowed. The server applies the write
+    /// as a whole, so turns away one larger in total than its
+    /// `max_request_len`, 1 GiB by default.
+    pub chunk_rows: Option<usize>,
+}
+
+impl WriteOptions {
+    pub fn new(mode: WriteMode) -> Self {
+        Self {
+            mode,
+            durability: Durability::Always,
+            token: None,
+            chunk_rows: None,
+        }
+    }
+}
+
+/// How a [`Client`] retries a failed request: after `initial`, then after
+/// twice as long each time, up to `max`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// How many times to retry before returning the error.
+    pub retries: u32,
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Backoff {
+    /// How long to wait before each retry.
+    fn delays(&self) -> impl Iterator<Item = Duration> {
+        let max = self.max;
+        std::iter::successors(Some(self.initial.min(max)), move |&delay| {
+            Some((delay * 2).min(max))
+        })
+        .take(self.retries as usize)
+    }
+}
+
+/// How long a [`Client`] waits on the server before failing with
+/// [`Error::Timeout`]; `None` waits indefinitely.
+#[derive(Debug, Clone, Default)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    /// For each response, from when the request is sent. A
+    /// [`Subscription`] waits for events indefinitely regardless.
+    pub read: Option<Duration>,
+    /// For each request to be taken by the connection.
+    pub write: Option<Duration>,
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("server error: {0}")]
-    Server(String),
+    #[error("server error: {message}")]
+    Server { code: ErrorCode, message: String },
+
+    /// The server answered a different request than the one sent.
+    #[error("expected a response to request {expected}, got one to {got}")]
+    MismatchedResponse { expected: u64, got: u64 },
+
+    /// No token was given, or the server rejected it or its permissions.
+    #[error("unauthenticated")]
+    Unauthenticated,
+
+    /// The server is shutting down and closed the connection without reading
+    /// the request, which may be sent again once it, or another server, is
+    /// back.
+    #[error("server going away")]
+    GoAway,
+
+    /// The server didn't accept the connection, take the request or answer
+    /// within the [`Timeouts`].
+    #[error("timed out")]
+    Timeout,
+
+    /// A TLS server name that is neither a DNS name nor an IP address.
+    #[error("invalid server name {0:?}")]
+    ServerName(String),
+
+    #[error(transparent)]
+    Tls(#[from] rustls::Error),
 
     #[error(transparent)]
     Proto(#[from] zola_db_proto::Error),
@@ -17,25 +153,244 @@ pub enum Error {
     Io(#[from] std::io::Error),
 }
 
+/// A connection to a server.
+pub trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Unpin> Stream for S {}
+
+type Connector = Box<
+    dyn Fn() -> Pin<Box<dyn Future<Output = io::Result<Box<dyn Stream>>> + Send>> + Send + Sync,
+>;
+
 pub struct Client {
-    addr: String,
+    /// Opens a connection per request.
+    connector: Connector,
+    token: Option<String>,
+    namespace: Option<String>,
+    compression: Compression,
+    /// `None` returns the first error.
+    backoff: Option<Backoff>,
+    timeouts: Timeouts,
+    /// The ID of the next request, echoed by the server in its response.
+    next_id: AtomicU64,
+}
+
+/// The options of a [`Client`], from [`Client::builder`].
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    token: Option<String>,
+    namespace: Option<String>,
+    compression: Compression,
+    backoff: Option<Backoff>,
+    timeouts: Timeouts,
+    nodelay: bool,
+    tls: Option<Tls>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            token: None,
+            namespace: None,
+            compression: Compression::None,
+            backoff: None,
+            timeouts: Timeouts::default(),
+            nodelay: true,
+            tls: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Authenticates every connection with `token`, for servers that require
+    /// one.
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Sends requests to the database the server serves as `namespace`,
+    /// rather than its default one.
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Asks the server to compress large messages on each connection with
+    /// `compression`, and compresses large requests if it agrees. This costs
+    /// a round trip per connection, so suits slow links.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Retries requests that fail with `backoff`, reconnecting each time:
+    /// any request if the server couldn't be reached or sent
+    /// [`Error::GoAway`], and once sent only those that are safe to repeat —
+    /// reads, and writes with a [`WriteOptions::token`] — if the connection
+    /// broke or the error [is retryable](ErrorCode::is_retryable).
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// Fails requests with [`Error::Timeout`] once the server takes longer
+    /// than `timeouts`.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Whether to disable Nagle's algorithm on TCP connections, so small
+    /// requests go out at once; on by default.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Connects over TLS as `tls` says, to a server behind a proxy that
+    /// terminates TLS. Applies to connections [`ClientBuilder::build`]
+    /// makes; [`ClientBuilder::build_with`] opens its own.
+    pub fn tls(mut self, tls: Tls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// A client of the server at `addr`, a TCP address or `unix:<path>` for
+    /// a Unix domain socket. Connections are made per request, so this
+    /// doesn't connect.
+    pub fn build(self, addr: impl Into<String>) -> Client {
+        let addr: String = addr.into();
+        let nodelay = self.nodelay;
+        let tls = self.tls.clone();
+        self.build_with(move || dial(addr.clone(), nodelay, tls.clone()))
+    }
+
+    /// A client that opens each connection with `connect`, e.g. over TLS or
+    /// an SSH tunnel, or to an in-memory pipe whose other end a server in
+    /// the same process serves.
+    pub fn build_with<F, Fut, S>(self, connect: F) -> Client
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<S>> + Send + 'static,
+        S: Stream + 'static,
+    {
+        Client {
+            connector: Box::new(move || {
+                let connect = connect();
+                Box::pin(async move { Ok(Box::new(connect.await?) as Box<dyn Stream>) })
+            }),
+            token: self.token,
+            namespace: self.namespace,
+            compression: self.compression,
+            backoff: self.backoff,
+            timeouts: self.timeouts,
+            next_id: AtomicU64::new(0),
+        }
+    }
 }
 
 impl Client {
+    /// A client of the server at `addr` with the default options.
     pub fn new(addr: impl Into<String>) -> Self {
-        Self { addr: addr.into() }
+        Self::builder().build(addr)
+    }
+
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
     }
 
     async fn request(&self, req: &Request) -> Result<Response, Error> {
-        let mut stream = TcpStream::connect(&self.addr).await?;
-        stream.set_nodelay(true)?;
-        zola_db_proto::write_request(&mut stream, req).await?;
+        let mut delays = self.backoff.iter().flat_map(Backoff::delays);
+        loop {
+            let e = match self.connect().await {
+                Ok(stream) => match self.exchange(stream, req).await {
+                    Err(e) if retryable(req, &e) => e,
+                    result => return result,
+                },
+                // Nothing was sent.
+                Err(e) => e,
+            };
+            match delays.next() {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(e),
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<Box<dyn Stream>, Error> {
+        let connect = async { Ok((self.connector)().await?) };
+        within(self.timeouts.connect, connect).await
+    }
+
+    async fn exchange(&self, stream: Box<dyn Stream>, req: &Request) -> Result<Response, Error> {
+        let (mut stream, compression, auth_id, id) = self.send(stream, req).await?;
         stream.shutdown().await?;
-        let resp = zola_db_proto::read_response(&mut stream).await?;
-        match resp {
-            Response::Error(msg) => Err(Error::Server(msg)),
-            other => Ok(other),
+        let read = receive(&mut stream, compression, auth_id, id);
+        within(self.timeouts.read, read).await
+    }
+
+    /// Writes `req` with `id` on `stream`.
+    async fn write_request(
+        &self,
+        stream: &mut (impl AsyncWrite + Unpin),
+        id: u64,
+        req: &Request,
+        compression: Compression,
+    ) -> Result<(), Error> {
+        let namespace = self.namespace.as_deref();
+        let write = zola_db_proto::write_request(stream, id, namespace, req, compression);
+        within(self.timeouts.write, async { Ok(write.await?) }).await
+    }
+
+    /// Sends `req` on `stream`, after authenticating if there is a token.
+    /// Returns the connection, its compression and the IDs of the two
+    /// requests.
+    async fn send(
+        &self,
+        mut stream: Box<dyn Stream>,
+        req: &Request,
+    ) -> Result<(Box<dyn Stream>, Compression, Option<u64>, u64), Error> {
+        let (compression, auth_id) = self.start(&mut stream).await?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.write_request(&mut stream, id, req, compression)
+            .await?;
+        Ok((stream, compression, auth_id, id))
+    }
+
+    /// Negotiates compression on a new connection, if asked to, and sends
+    /// the token, if there is one. Returns the compression to use and the ID
+    /// of the token's request, whose response is left to read.
+    async fn start(
+        &self,
+        stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    ) -> Result<(Compression, Option<u64>), Error> {
+        let mut compression = Compression::None;
+        if self.compression != Compression::None {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let negotiate = Request::Negotiate {
+                compression: vec![self.compression],
+            };
+            self.write_request(stream, id, &negotiate, compression)
+                .await?;
+            let read = read_response(stream, compression, id);
+            match check(within(self.timeouts.read, read).await?)? {
+                Response::Negotiate(chosen) => compression = chosen,
+                _ => unreachable!(),
+            }
         }
+        let auth_id = match &self.token {
+            Some(token) => {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                let auth = Request::Auth {
+                    token: token.clone(),
+                };
+                self.write_request(stream, id, &auth, compression).await?;
+                Some(id)
+            }
+            None => None,
+        };
+        Ok((compression, auth_id))
     }
 
     pub async fn join_asof(
@@ -57,6 +412,52 @@ impl Client {
         }
     }
 
+    /// Runs each of `requests` as [`Client::join_asof`] does, returning their
+    /// results in order, or the first error. They are all sent on one
+    /// connection without waiting for responses, so thousands of small
+    /// joins cost about one round trip rather than one each. Not retried.
+    pub async fn asof_batch(&self, requests: &[AsofRequest]) -> Result<Vec<RecordBatch>, Error> {
+        let mut stream = self.connect().await?;
+        let (compression, auth_id) = self.start(&mut stream).await?;
+        let ids: Vec<u64> = (requests.iter())
+            .map(|_| self.next_id.fetch_add(1, Ordering::Relaxed))
+            .collect();
+        // Written while responses are read, so neither side blocks on a full
+        // socket buffer.
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let write = async {
+            for (&id, r) in ids.iter().zip(requests) {
+                let req = Request::JoinAsof {
+                    table: r.table.clone(),
+                    symbol: r.symbol.clone(),
+                    direction: r.direction,
+                    timestamps: r.timestamps.clone(),
+                };
+                self.write_request(&mut writer, id, &req, compression)
+                    .await?;
+            }
+            writer.shutdown().await?;
+            Ok::<_, Error>(())
+        };
+        let read = async {
+            if let Some(id) = auth_id {
+                let read = read_response(&mut reader, compression, id);
+                check(within(self.timeouts.read, read).await?)?;
+            }
+            let mut batches = Vec::with_capacity(ids.len());
+            for &id in &ids {
+                let read = read_response(&mut reader, compression, id);
+                match check(within(self.timeouts.read, read).await?)? {
+                    Response::JoinAsof(batch) => batches.push(batch),
+                    _ => unreachable!(),
+                }
+            }
+            Ok::<_, Error>(batches)
+        };
+        let ((), batches) = tokio::try_join!(write, read)?;
+        Ok(batches)
+    }
+
     pub async fn ingest_binance(
         &self,
         market: Market,
@@ -68,4 +469,353 @@ impl Client {
             _ => unreachable!(),
         }
     }
+
+    /// Stores `batch` as rows of `table` for `day`, which must hold all of
+    /// its timestamps, and returns once it is synced to disk on the server.
+    /// Needs write rights on `table`.
+    pub async fn write(
+        &self,
+        table: &str,
+        day: jiff::civil::Date,
+        batch: &RecordBatch,
+        mode: WriteMode,
+    ) -> Result<(), Error> {
+        self.write_with(table, day, batch, &WriteOptions::new(mode))
+            .await
+    }
+
+    /// Like [`Client::write`], with additional [`WriteOptions`].
+    pub async fn write_with(
+        &self,
+        table: &str,
+        day: jiff::civil::Date,
+        batch: &RecordBatch,
+        options: &WriteOptions,
+    ) -> Result<(), Error> {
+        let req = Request::Write {
+            table: table.to_string(),
+            day,
+            mode: options.mode,
+            durability: options.durability,
+            token: options.token.clone(),
+            batches: chunks(batch, options.chunk_rows),
+        };
+        match self.request(&req).await? {
+            Response::Write => Ok(()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Drains the server for a restart: it stops accepting connections and
+    /// exits once the requests in flight are answered. Needs admin rights on
+    /// every table of every namespace.
+    pub async fn drain(&self) -> Result<(), Error> {
+        match self.request(&Request::Drain).await? {
+            Response::Drain => Ok(()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Checks that the server is up and answering, and that it accepts the
+    /// token if there is one. The latency is timed on a connection already
+    /// open, so excludes connecting. Not retried.
+    pub async fn ping(&self) -> Result<Pong, Error> {
+        let mut stream = self.connect().await?;
+        let (compression, auth_id) = self.start(&mut stream).await?;
+        if let Some(id) = auth_id {
+            let read = read_response(&mut stream, compression, id);
+            check(within(self.timeouts.read, read).await?)?;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let sent = Instant::now();
+        self.write_request(&mut stream, id, &Request::Ping, compression)
+            .await?;
+        let read = read_response(&mut stream, compression, id);
+        match check(within(self.timeouts.read, read).await?)? {
+            Response::Ping(version) => Ok(Pong {
+                version,
+                latency: sent.elapsed(),
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Lists the tables this client may read, in order of name.
+    pub async fn list_tables(&self) -> Result<Vec<TableInfo>, Error> {
+        match self.request(&Request::ListTables).await? {
+            Response::ListTables(tables) => Ok(tables),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The schema of `table`, whose value columns query results carry.
+    pub async fn schema(&self, table: &str) -> Result<SchemaRef, Error> {
+        let req = Request::GetSchema {
+            table: table.to_string(),
+        };
+        match self.request(&req).await? {
+            Response::GetSchema(schema) => Ok(schema),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The rows of `symbol` in `table` with timestamps in `range`, in time
+    /// order, with the same columns as [`Client::join_asof`] — or only
+    /// `columns`, in the order given.
+    pub async fn range(
+        &self,
+        table: &str,
+        symbol: &str,
+        range: std::ops::Range<i64>,
+        columns: Option<&[&str]>,
+    ) -> Result<RecordBatch, Error> {
+        let req = Request::Range {
+            table: table.to_string(),
+            symbol: symbol.to_string(),
+            start: range.start,
+            end: range.end,
+            columns: columns.map(|c| c.iter().map(|s| s.to_string()).collect()),
+        };
+        match self.request(&req).await? {
+            Response::Range(batch) => Ok(batch),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Deletes `table` and its data on the server.
+    pub async fn drop_table(&self, table: &str) -> Result<(), Error> {
+        let req = Request::DropTable {
+            table: table.to_string(),
+        };
+        match self.request(&req).await? {
+            Response::DropTable => Ok(()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Lists the partitions of the tables this client may read, in order of
+    /// table and day.
+    pub async fn list_partitions(&self) -> Result<Vec<PartitionInfo>, Error> {
+        match self.request(&Request::ListPartitions).await? {
+            Response::ListPartitions(partitions) => Ok(partitions),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The rows of the partition of `table` for `day`, as stored.
+    pub async fn partition(
+        &self,
+        table: &str,
+        day: jiff::civil::Date,
+    ) -> Result<RecordBatch, Error> {
+        let req = Request::GetPartition {
+            table: table.to_string(),
+            day,
+        };
+        match self.request(&req).await? {
+            Response::GetPartition(batch) => Ok(batch),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Bytes `offset..offset + len` of the file of the partition of `table`
+    /// for `day`, cut short at its end or at the server's limit on a chunk,
+    /// if the partition's checksum is still `checksum`, as
+    /// [`Client::list_partitions`] gave it.
+    pub async fn read_partition_file(
+        &self,
+        table: &str,
+        day: jiff::civil::Date,
+        checksum: u32,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, Error> {
+        let req = Request::ReadPartitionFile {
+            table: table.to_string(),
+            day,
+            checksum,
+            offset,
+            len,
+        };
+        match self.request(&req).await? {
+            Response::ReadPartitionFile(bytes) => Ok(bytes),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Subscribes to the writes committed to `table` on the server, only the
+    /// rows of `symbols` if given. Needs read rights on `table`.
+    pub async fn subscribe(
+        &self,
+        table: &str,
+        symbols: Option<&[&str]>,
+    ) -> Result<Subscription, Error> {
+        let req = Request::Subscribe {
+            table: table.to_string(),
+            symbols: symbols.map(|s| s.iter().map(|s| s.to_string()).collect()),
+        };
+        let stream = self.connect().await?;
+        let (mut stream, compression, auth_id, id) = self.send(stream, &req).await?;
+        let read = receive(&mut stream, compression, auth_id, id);
+        match within(self.timeouts.read, read).await? {
+            Response::Subscribe => Ok(Subscription {
+                stream,
+                compression,
+                id,
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Has the server register partitions that other processes wrote to its
+    /// data directory, returning the (table, day) of each. Needs admin rights
+    /// on all tables.
+    pub async fn refresh(&self) -> Result<Vec<(String, jiff::civil::Date)>, Error> {
+        match self.request(&Request::Refresh).await? {
+            Response::Refresh(added) => Ok(added),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Deletes the partitions of `table` for days before `day`, e.g. to
+    /// enforce a retention period, returning their days. Needs admin rights
+    /// on `table`.
+    pub async fn drop_partitions_before(
+        &self,
+        table: &str,
+        day: jiff::civil::Date,
+    ) -> Result<Vec<jiff::civil::Date>, Error> {
+        let req = Request::DropPartitionsBefore {
+            table: table.to_string(),
+            day,
+        };
+        match self.request(&req).await? {
+            Response::DropPartitionsBefore(days) => Ok(days),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The writes to a table, from [`Client::subscribe`], on a connection of its
+/// own.
+pub struct Subscription {
+    stream: Box<dyn Stream>,
+    compression: Compression,
+    id: u64,
+}
+
+impl Subscription {
+    /// Waits for the matching rows of the next write. Fails once the
+    /// subscription ends, with [`Error::GoAway`] if the server is shutting
+    /// down, or because this subscriber fell too far behind.
+    pub async fn next(&mut self) -> Result<RecordBatch, Error> {
+        let read = read_response(&mut self.stream, self.compression, self.id);
+        match check(read.await?)? {
+            Response::Event(batch) => Ok(batch),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Reads the responses to the requests [`Client::send`] sent.
+async fn receive(
+    stream: &mut (impl AsyncRead + Unpin),
+    compression: Compression,
+    auth_id: Option<u64>,
+    id: u64,
+) -> Result<Response, Error> {
+    let auth = match auth_id {
+        Some(id) => Some(read_response(stream, compression, id).await?),
+        None => None,
+    };
+    let resp = read_response(stream, compression, id).await?;
+    auth.map(check).transpose()?;
+    check(resp)
+}
+
+async fn read_response(
+    stream: &mut (impl AsyncRead + Unpin),
+    compression: Compression,
+    expected: u64,
+) -> Result<Response, Error> {
+    let (got, resp) = zola_db_proto::read_response(stream, compression).await?;
+    if let Response::GoAway = resp {
+        return Err(Error::GoAway);
+    }
+    if got != expected {
+        return Err(Error::MismatchedResponse { expected, got });
+    }
+    Ok(resp)
+}
+
+/// Connects to `addr`, as [`ClientBuilder::build`] takes it.
+async fn dial(addr: String, nodelay: bool, tls: Option<Tls>) -> io::Result<Box<dyn Stream>> {
+    let stream: Box<dyn Stream> = match addr.strip_prefix("unix:") {
+        #[cfg(unix)]
+        Some(path) => Box::new(UnixStream::connect(path).await?),
+        _ => {
+            let stream = TcpStream::connect(&addr).await?;
+            stream.set_nodelay(nodelay)?;
+            Box::new(stream)
+        }
+    };
+    match tls {
+        Some(tls) => tls.connect(&addr, stream).await,
+        None => Ok(stream),
+    }
+}
+
+/// `batch` in slices of `rows` rows, or whole if `None`.
+fn chunks(batch: &RecordBatch, rows: Option<usize>) -> Vec<RecordBatch> {
+    let len = batch.num_rows();
+    match rows {
+        Some(rows) if len > 0 => (0..len)
+            .step_by(rows.max(1))
+            .map(|offset| batch.slice(offset, rows.clamp(1, len - offset)))
+            .collect(),
+        _ => vec![batch.clone()],
+    }
+}
+
+/// Runs `f`, failing with [`Error::Timeout`] if it takes longer than `limit`.
+async fn within<T>(
+    limit: Option<Duration>,
+    f: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, f)
+            .await
+            .map_err(|_| Error::Timeout)?,
+        None => f.await,
+    }
+}
+
+/// Whether `req` may be sent again after failing with `e` once sent: if the
+/// server didn't read it, or it is safe to repeat.
+fn retryable(req: &Request, e: &Error) -> bool {
+    let repeatable = match req {
+        Request::Write { token, .. } => token.is_some(),
+        Request::JoinAsof { .. }
+        | Request::ListTables
+        | Request::GetSchema { .. }
+        | Request::Range { .. }
+        | Request::ListPartitions
+        | Request::GetPartition { .. }
+        | Request::ReadPartitionFile { .. } => true,
+        _ => false,
+    };
+    match e {
+        Error::GoAway => true,
+        Error::Io(_) | Error::Proto(zola_db_proto::Error::Io(_)) | Error::Timeout => repeatable,
+        Error::Server { code, .. } => repeatable && code.is_retryable(),
+        _ => false,
+    }
+}
+
+fn check(resp: Response) -> Result<Response, Error> {
+    match resp {
+        Response::Error(code, message) => Err(Error::Server { code, message }),
+        Response::Unauthenticated => Err(Error::Unauthenticated),
+        other => Ok(other),
+    }
 }
@@ -0,0 +1,165 @@
+use std::io;
+use std::sync::Arc;
+
+use rustls::client::WantsClientCert;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, ConfigBuilder, DigitallySignedStruct, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use tokio_rustls::TlsConnector;
+
+use crate::{Error, Stream};
+
+/// How a [`Client`](crate::Client) secures its connections, for
+/// [`ClientBuilder::tls`](crate::ClientBuilder::tls).
+#[derive(Debug, Clone)]
+pub struct Tls {
+    config: Arc<ClientConfig>,
+    server_name: Option<ServerName<'static>>,
+}
+
+impl Tls {
+    /// Verifies the server's certificate for the host of its address against
+    /// `roots`, DER-encoded CA certificates, or against the system's CAs if
+    /// there are none.
+    pub fn new(roots: &[Vec<u8>]) -> Result<Self, Error> {
+        let mut store = rustls::RootCertStore::empty();
+        if roots.is_empty() {
+            let native = rustls_native_certs::load_native_certs();
+            if let (true, Some(e)) = (native.certs.is_empty(), native.errors.into_iter().next()) {
+                return Err(Error::Io(io::Error::other(e)));
+            }
+            store.add_parsable_certificates(native.certs);
+        } else {
+            for root in roots {
+                store.add(CertificateDer::from(root.clone()))?;
+            }
+        }
+        let config = builder().with_root_certificates(store);
+        Ok(Self::finish(config))
+    }
+
+    /// Trusts a server whose certificate has one of `pins`, the SHA-256
+    /// digests of DER-encoded public keys (SubjectPublicKeyInfo), for servers
+    /// with no CA to vouch for them. Who issued the certificate, its names
+    /// and its validity period are not checked, so it may be self-signed.
+    pub fn pinned(pins: Vec<[u8; 32]>) -> Self {
+        let provider = provider();
+        let verifier = Pinned {
+            pins,
+            algorithms: provider.signature_verification_algorithms,
+        };
+        let config = builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier));
+        Self::finish(config)
+    }
+
+    /// Sends `name` as the server's name, and verifies its certificate for
+    /// it, rather than the host of its address. Needed for Unix domain
+    /// sockets.
+    pub fn server_name(mut self, name: &str) -> Result<Self, Error> {
+        let name = ServerName::try_from(name.to_string())
+            .map_err(|_| Error::ServerName(name.to_string()))?;
+        self.server_name = Some(name);
+        Ok(self)
+    }
+
+    fn finish(builder: ConfigBuilder<ClientConfig, WantsClientCert>) -> Self {
+        Self {
+            config: Arc::new(builder.with_no_client_auth()),
+            server_name: None,
+        }
+    }
+
+    /// Starts TLS on `stream`, a connection to `addr`.
+    pub(crate) async fn connect(
+        &self,
+        addr: &str,
+        stream: Box<dyn Stream>,
+    ) -> io::Result<Box<dyn Stream>> {
+        let name = match &self.server_name {
+            Some(name) => name.clone(),
+            None => host(addr)?,
+        };
+        let connector = TlsConnector::from(Arc::clone(&self.config));
+        Ok(Box::new(connector.connect(name, stream).await?))
+    }
+}
+
+fn provider() -> CryptoProvider {
+    rustls::crypto::aws_lc_rs::default_provider()
+}
+
+fn builder() -> ConfigBuilder<ClientConfig, rustls::WantsVerifier> {
+    ClientConfig::builder_with_provider(Arc::new(provider()))
+        .with_safe_default_protocol_versions()
+        .expect("the default provider supports the default protocol versions")
+}
+
+/// The host of the TCP address `addr`, as the server's name.
+fn host(addr: &str) -> io::Result<ServerName<'static>> {
+    let host = match addr.rsplit_once(':') {
+        Some((host, _)) if !addr.starts_with("unix:") => host,
+        _ => {
+            let msg = format!("no server name for TLS to {addr:?}; set one with Tls::server_name");
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    ServerName::try_from(host.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Trusts the certificates with a pinned public key.
+#[derive(Debug)]
+struct Pinned {
+    pins: Vec<[u8; 32]>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer,
+        _intermediates: &[CertificateDer],
+        _server_name: &ServerName,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let cert = webpki::EndEntityCert::try_from(end_entity)
+            .map_err(|_| CertificateError::BadEncoding)?;
+        let digest: [u8; 32] = Sha256::digest(cert.subject_public_key_info()).into();
+        if !self.pins.contains(&digest) {
+            return Err(CertificateError::ApplicationVerificationFailure.into());
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    // The handshake is still checked, so that only the holder of a pinned
+    // key's private half can pass.
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
@@ -12,8 +12,37 @@ pub enum Market {
     Perp,
 }
 
+/// How a write treats an existing partition for the same day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteMode {
+    /// Fail with a partition-exists error.
+    ErrorIfExists,
+    /// Replace the partition.
+    Overwrite,
+    /// Merge the new rows into the partition, keeping each key's rows in
+    /// timestamp order; existing rows sort first among equal timestamps.
+    Append,
+}
+
+/// When a write forces the partitions it wrote to stable storage.
+///
+/// There is no per-file policy between the two: a partition is a single
+/// file, so `Always` already syncs the least that keeps each one intact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Durability {
+    /// Before returning: a partition survives a crash or power loss once
+    /// written, at the cost of an fsync of each partition file written and
+    /// then of the table directory, however many days the write spans.
+    /// On Windows only the files are synced.
+    Always,
+    /// Whenever the OS flushes. A crash can lose recent writes; bulk loads
+    /// that can be replayed may prefer the throughput.
+    Never,
+}
+
 const SECONDS_PER_DAY: i64 = 86_400;
-const MICROS_PER_DAY: i64 = SECONDS_PER_DAY * 1_000_000;
+
+pub const MICROS_PER_DAY: i64 = SECONDS_PER_DAY * 1_000_000;
 
 pub const SYMBOL_COL: &str = "symbol";
 pub const TIMESTAMP_COL: &str = "timestamp";
@@ -25,6 +54,27 @@ impl EpochDay {
     pub fn from_timestamp_us(us: i64) -> Self {
         Self(us.div_euclid(MICROS_PER_DAY) as i32)
     }
+
+    /// Returns the timestamp of this day's midnight, in microseconds.
+    pub fn start_timestamp_us(self) -> i64 {
+        self.0 as i64 * MICROS_PER_DAY
+    }
+
+    /// Like [`EpochDay::from_timestamp_us`], but for the local date in `tz`.
+    /// Timestamps outside the range of civil dates fall back to UTC.
+    pub fn from_timestamp_us_in(us: i64, tz: &jiff::tz::TimeZone) -> Self {
+        match jiff::Timestamp::from_microsecond(us) {
+            Ok(ts) => tz.to_datetime(ts).date().into(),
+            Err(_) => Self::from_timestamp_us(us),
+        }
+    }
+
+    /// Like [`EpochDay::start_timestamp_us`], but for local midnight in `tz` —
+    /// or the first instant after it, if midnight falls in a DST gap.
+    pub fn start_timestamp_us_in(self, tz: &jiff::tz::TimeZone) -> i64 {
+        let date: jiff::civil::Date = self.into();
+        date.to_zoned(tz.clone()).unwrap().timestamp().as_microsecond()
+    }
 }
 
 impl From<EpochDay> for jiff::civil::Date {
@@ -0,0 +1,122 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Fields, Ident, Type, parse_macro_input};
+
+/// Derives `zola_db::ZolaRow` for a struct with named fields: one `String`
+/// field marked `#[symbol]`, one `i64` field marked `#[timestamp]`, and value
+/// fields whose types implement `zola_db::RowValue`.
+#[proc_macro_derive(ZolaRow, attributes(symbol, timestamp))]
+pub fn derive_zola_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "ZolaRow cannot be derived for generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "ZolaRow requires a struct with named fields",
+                ));
+            }
+        },
+        _ => return Err(syn::Error::new_spanned(name, "ZolaRow requires a struct")),
+    };
+
+    let mut symbol: Option<&Ident> = None;
+    let mut timestamp: Option<&Ident> = None;
+    let mut values: Vec<(&Ident, &Type)> = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let is_symbol = field.attrs.iter().any(|a| a.path().is_ident("symbol"));
+        let is_timestamp = field.attrs.iter().any(|a| a.path().is_ident("timestamp"));
+        let slot = match (is_symbol, is_timestamp) {
+            (true, true) => {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "a field cannot be both #[symbol] and #[timestamp]",
+                ));
+            }
+            (true, false) => &mut symbol,
+            (false, true) => &mut timestamp,
+            (false, false) => {
+                values.push((ident, &field.ty));
+                continue;
+            }
+        };
+        if slot.replace(ident).is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "duplicate #[symbol] or #[timestamp] field",
+            ));
+        }
+    }
+    let symbol =
+        symbol.ok_or_else(|| syn::Error::new_spanned(name, "missing a #[symbol] field"))?;
+    let timestamp =
+        timestamp.ok_or_else(|| syn::Error::new_spanned(name, "missing a #[timestamp] field"))?;
+
+    let p = quote!(::zola_db::__private);
+    let value_names: Vec<String> = values.iter().map(|(ident, _)| ident.to_string()).collect();
+    let value_idents: Vec<&Ident> = values.iter().map(|(ident, _)| *ident).collect();
+    let value_types: Vec<&Type> = values.iter().map(|(_, ty)| *ty).collect();
+    let value_columns: Vec<Ident> = value_idents
+        .iter()
+        .map(|ident| format_ident!("__column_{}", ident))
+        .collect();
+
+    Ok(quote! {
+        impl ::zola_db::ZolaRow for #name {
+            fn schema() -> #p::SchemaRef {
+                ::std::sync::Arc::new(#p::Schema::new(::std::vec![
+                    #p::symbol_field(),
+                    #p::field::<i64>(::zola_db::TIMESTAMP_COL),
+                    #(#p::field::<#value_types>(#value_names),)*
+                ]))
+            }
+
+            fn to_batch(rows: &[Self]) -> ::core::result::Result<#p::RecordBatch, #p::ArrowError> {
+                #p::RecordBatch::try_new(
+                    <Self as ::zola_db::ZolaRow>::schema(),
+                    ::std::vec![
+                        #p::encode_symbols(rows.iter().map(|r| r.#symbol.as_str()))?,
+                        <i64 as ::zola_db::RowValue>::to_array(rows.iter().map(|r| r.#timestamp)),
+                        #(<#value_types as ::zola_db::RowValue>::to_array(
+                            rows.iter().map(|r| ::std::clone::Clone::clone(&r.#value_idents)),
+                        ),)*
+                    ],
+                )
+            }
+
+            fn decode(batch: &#p::RecordBatch) -> ::core::result::Result<::std::vec::Vec<::core::option::Option<Self>>, #p::ArrowError> {
+                let __timestamps = #p::column::<i64>(batch, ::zola_db::TIMESTAMP_COL)?;
+                let __symbols = #p::symbols(batch)?;
+                #(let #value_columns = #p::column::<#value_types>(batch, #value_names)?;)*
+                (0..batch.num_rows())
+                    .map(|i| {
+                        if __timestamps.is_null(i) {
+                            return ::core::result::Result::Ok(::core::option::Option::None);
+                        }
+                        ::core::result::Result::Ok(::core::option::Option::Some(Self {
+                            #symbol: __symbols.as_ref().map_or_else(::std::string::String::new, |s| s[i].clone()),
+                            #timestamp: #p::read::<i64>(__timestamps, i, ::zola_db::TIMESTAMP_COL)?,
+                            #(#value_idents: #p::read::<#value_types>(#value_columns, i, #value_names)?,)*
+                        }))
+                    })
+                    .collect()
+            }
+        }
+    })
+}
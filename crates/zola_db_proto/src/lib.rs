@@ -1,6 +1,12 @@
+use std::io::Read;
+use std::time::Duration;
+
+use arrow::datatypes::SchemaRef;
 use arrow::ipc::{reader::StreamReader, writer::StreamWriter};
 use arrow::record_batch::RecordBatch;
+use bytes::Buf;
 use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 #[derive(Debug, thiserror::Error)]
@@ -15,7 +21,7 @@ pub enum Error {
     Arrow(#[from] arrow::error::ArrowError),
 }
 
-pub use zola_db_core::{Direction, Market};
+pub use zola_db_core::{Direction, Durability, Market, WriteMode};
 
 pub enum Request {
     JoinAsof {
@@ -28,12 +34,207 @@ pub enum Request {
         market: Market,
         day: jiff::civil::Date,
     },
+    /// Authenticates the connection; required before other requests when the
+    /// server has tokens configured.
+    Auth {
+        token: String,
+    },
+    /// Lists the tables the connection may read.
+    ListTables,
+    GetSchema {
+        table: String,
+    },
+    DropTable {
+        table: String,
+    },
+    /// The rows of `symbol` with timestamps in `start..end`, in time order;
+    /// only `columns`, if given.
+    Range {
+        table: String,
+        symbol: String,
+        start: i64,
+        end: i64,
+        columns: Option<Vec<String>>,
+    },
+    /// Registers partitions written to the server's data directory by other
+    /// processes; see `Db::refresh`.
+    Refresh,
+    /// Deletes the partitions of `table` for days before `day`.
+    DropPartitionsBefore {
+        table: String,
+        day: jiff::civil::Date,
+    },
+    /// Stores the rows of `batches`, which must share a schema, as the rows
+    /// of `table` for `day`, in one write as `Db::ingest` does. Each batch is
+    /// sent in a frame of its own, so a large write split into several needs
+    /// neither one huge frame nor its encoding in memory at once. There may
+    /// be at most [`MAX_WRITE_CHUNKS`] of them, and the server limits their
+    /// size with its [`Limits`].
+    ///
+    /// The response comes once the partition is written, and with
+    /// [`Durability::Always`] only once it is also synced to disk.
+    ///
+    /// A write with a `token` the server recently applied a write to the same
+    /// table and day with is answered without being applied again, so that a
+    /// client unsure whether a write went through can send it again. Tokens
+    /// are at most [`MAX_WRITE_TOKEN_LEN`] bytes; the server forgets the
+    /// oldest, and all on restart.
+    Write {
+        table: String,
+        day: jiff::civil::Date,
+        mode: WriteMode,
+        durability: Durability,
+        token: Option<String>,
+        batches: Vec<RecordBatch>,
+    },
+    /// Lists the partitions of the tables the connection may read, e.g. for
+    /// a replica to find what changed.
+    ListPartitions,
+    GetPartition {
+        table: String,
+        day: jiff::civil::Date,
+    },
+    /// Bytes `offset..offset + len` of the file of a partition, cut short at
+    /// its end or at the server's limit on a chunk, if its checksum is still
+    /// `checksum`; see
+    /// `Db::read_partition_file`. Copying a file a chunk at a time lets a
+    /// transfer resume where it broke off.
+    ReadPartitionFile {
+        table: String,
+        day: jiff::civil::Date,
+        checksum: u32,
+        offset: u64,
+        len: u64,
+    },
+    /// Subscribes the connection to the writes committed to `table`, only the
+    /// rows of `symbols` if given. After the [`Response::Subscribe`], each
+    /// arrives as a [`Response::Event`] to this request, and the connection
+    /// carries nothing else.
+    Subscribe {
+        table: String,
+        symbols: Option<Vec<String>>,
+    },
+    /// Offers the compression methods the client accepts, in order of
+    /// preference. Once the server answers with its choice, both sides may
+    /// compress large frames with it for the rest of the connection.
+    Negotiate {
+        compression: Vec<Compression>,
+    },
+    /// Drains the server for a restart: it stops accepting connections,
+    /// answers the requests in flight, sends each connection a
+    /// [`Response::GoAway`] and exits. Needs admin rights on every table of
+    /// every namespace.
+    Drain,
+    /// Asks the server to answer, to check that it is up. Needs no rights,
+    /// nor an existing namespace.
+    Ping,
 }
 
 pub enum Response {
     JoinAsof(RecordBatch),
     IngestBinance,
-    Error(String),
+    Auth,
+    /// The connection has not authenticated, or its token does not permit the
+    /// request.
+    Unauthenticated,
+    ListTables(Vec<TableInfo>),
+    GetSchema(SchemaRef),
+    DropTable,
+    Range(RecordBatch),
+    /// The (table, day) of each partition found.
+    Refresh(Vec<(String, jiff::civil::Date)>),
+    /// The days of the partitions deleted.
+    DropPartitionsBefore(Vec<jiff::civil::Date>),
+    Write,
+    ListPartitions(Vec<PartitionInfo>),
+    GetPartition(RecordBatch),
+    ReadPartitionFile(Vec<u8>),
+    Subscribe,
+    /// The matching rows of a write committed to a subscribed table.
+    Event(RecordBatch),
+    Negotiate(Compression),
+    Drain,
+    /// The server's version.
+    Ping(String),
+    /// Sent unprompted, with ID [`GOAWAY_ID`], before the server closes a
+    /// connection as it shuts down. Requests sent after the last response
+    /// were not read, so may be sent again to another server.
+    GoAway,
+    Error(ErrorCode, String),
+}
+
+/// The request ID a [`Response::GoAway`] is sent with, as it answers none.
+pub const GOAWAY_ID: u64 = u64::MAX;
+
+/// The longest token a [`Request::Write`] may have, in bytes.
+pub const MAX_WRITE_TOKEN_LEN: usize = 256;
+
+/// What kind of failure a [`Response::Error`] reports, so that clients can
+/// tell e.g. a missing table, where retrying is pointless, from a server
+/// shutting down, where it is not.
+///
+/// Sent as its number, a `u16`; a client that reads one it doesn't know
+/// fails to decode the response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u16)]
+pub enum ErrorCode {
+    /// The table, partition or namespace does not exist.
+    NotFound = 1,
+    AlreadyExists = 2,
+    /// The request is malformed, e.g. a batch with the wrong schema or
+    /// unsorted rows.
+    InvalidInput = 3,
+    QuotaExceeded = 4,
+    /// The database, or the whole server, does not take writes.
+    ReadOnly = 5,
+    /// Something the request depends on changed meanwhile, e.g. the
+    /// partition a file chunk was asked of.
+    Conflict = 6,
+    /// The server can't serve the request now, e.g. because it is shutting
+    /// down or an upstream source failed.
+    Unavailable = 7,
+    Internal = 8,
+}
+
+impl ErrorCode {
+    /// Whether the same request may succeed if sent again, perhaps after
+    /// fetching what changed.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorCode::Conflict | ErrorCode::Unavailable)
+    }
+}
+
+/// How frames are compressed on a connection, as negotiated with
+/// [`Request::Negotiate`]. Reads reject compressed frames unless it is
+/// [`Compression::Zstd`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+/// A table, as listed by [`Request::ListTables`].
+#[derive(Debug, Clone)]
+pub struct TableInfo {
+    pub name: String,
+    pub schema: SchemaRef,
+    /// The columns that identify a row within a timestamp, `symbol` first.
+    pub keys: Vec<String>,
+    /// The first and last days with a partition; `None` if there are none.
+    pub days: Option<(jiff::civil::Date, jiff::civil::Date)>,
+}
+
+/// A partition, as listed by [`Request::ListPartitions`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionInfo {
+    pub table: String,
+    pub day: jiff::civil::Date,
+    pub rows: u64,
+    /// The size of the partition's file.
+    pub bytes: u64,
+    /// The CRC-32 of the partition's file; see `Db::partitions`.
+    pub checksum: u32,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -47,39 +248,177 @@ enum RequestHeader {
         market: Market,
         day: jiff::civil::Date,
     },
+    Auth {
+        token: String,
+    },
+    ListTables,
+    GetSchema {
+        table: String,
+    },
+    DropTable {
+        table: String,
+    },
+    Range {
+        table: String,
+        symbol: String,
+        start: i64,
+        end: i64,
+        columns: Option<Vec<String>>,
+    },
+    Refresh,
+    DropPartitionsBefore {
+        table: String,
+        day: jiff::civil::Date,
+    },
+    Write {
+        table: String,
+        day: jiff::civil::Date,
+        mode: WriteMode,
+        durability: Durability,
+        token: Option<String>,
+        /// How many batches follow.
+        chunks: u32,
+    },
+    ListPartitions,
+    GetPartition {
+        table: String,
+        day: jiff::civil::Date,
+    },
+    ReadPartitionFile {
+        table: String,
+        day: jiff::civil::Date,
+        checksum: u32,
+        offset: u64,
+        len: u64,
+    },
+    Subscribe {
+        table: String,
+        symbols: Option<Vec<String>>,
+    },
+    Negotiate {
+        compression: Vec<Compression>,
+    },
+    Drain,
+    Ping,
 }
 
 #[derive(Serialize, Deserialize)]
 enum ResponseHeader {
     JoinAsof,
     IngestBinance,
-    Error(String),
+    Error(ErrorCode, String),
+    Auth,
+    Unauthenticated,
+    /// Followed by each table's schema, in order.
+    ListTables(Vec<TableHeader>),
+    /// Followed by the schema.
+    GetSchema,
+    DropTable,
+    /// Followed by the rows.
+    Range,
+    Refresh(Vec<(String, jiff::civil::Date)>),
+    DropPartitionsBefore(Vec<jiff::civil::Date>),
+    Write,
+    ListPartitions(Vec<PartitionInfo>),
+    /// Followed by the rows.
+    GetPartition,
+    /// Followed by the bytes.
+    ReadPartitionFile,
+    Subscribe,
+    /// Followed by the rows.
+    Event,
+    Negotiate(Compression),
+    Drain,
+    Ping(String),
+    GoAway,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TableHeader {
+    name: String,
+    keys: Vec<String>,
+    days: Option<(jiff::civil::Date, jiff::civil::Date)>,
 }
 
-async fn write_frame(w: &mut (impl AsyncWrite + Unpin), bytes: &[u8]) -> Result<(), Error> {
-    w.write_all(&(bytes.len() as u32).to_le_bytes()).await?;
-    w.write_all(bytes).await?;
+/// Set in a frame's length when its bytes are compressed.
+const COMPRESSED: u32 = 1 << 31;
+
+/// The longest frame the format allows, as its length has 31 bits.
+const MAX_FRAME_LEN: usize = COMPRESSED as usize - 1;
+
+/// Frames smaller than this are sent as they are.
+const COMPRESS_THRESHOLD: usize = 16 << 10;
+
+async fn write_frame(w: &mut (impl AsyncWrite + Unpin), compression: Compression, bytes: &[u8]) -> Result<(), Error> {
+    let compressed;
+    let (flag, bytes) = match compression {
+        Compression::Zstd if bytes.len() >= COMPRESS_THRESHOLD => {
+            compressed = zstd::encode_all(bytes, 0)?;
+            (COMPRESSED, &compressed[..])
+        }
+        _ => (0, bytes),
+    };
+    let len = u32::try_from(bytes.len())
+        .ok()
+        .filter(|&len| len < COMPRESSED)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "frame over 2 GiB"))?;
+    // One vectored write, so the length doesn't go out in a packet of its
+    // own on a connection with Nagle's algorithm off.
+    let len = (len | flag).to_le_bytes();
+    w.write_all_buf(&mut Buf::chain(&len[..], bytes)).await?;
     Ok(())
 }
 
-// No size cap on reads — the client is trusted (same-host only).
-async fn read_frame(r: &mut (impl AsyncRead + Unpin)) -> Result<Vec<u8>, Error> {
+async fn read_frame(r: &mut (impl AsyncRead + Unpin), compression: Compression) -> Result<Vec<u8>, Error> {
+    read_frame_opt(r, compression, MAX_FRAME_LEN)
+        .await?
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+}
+
+/// Reads a frame of at most `max_len` bytes once decompressed, or returns
+/// `None` if the stream ends before its first byte.
+async fn read_frame_opt(r: &mut (impl AsyncRead + Unpin), compression: Compression, max_len: usize) -> Result<Option<Vec<u8>>, Error> {
     let mut len_buf = [0u8; 4];
-    r.read_exact(&mut len_buf).await?;
-    let len = u32::from_le_bytes(len_buf) as usize;
-    let mut buf = vec![0u8; len];
+    if r.read(&mut len_buf[..1]).await? == 0 {
+        return Ok(None);
+    }
+    r.read_exact(&mut len_buf[1..]).await?;
+    let len = u32::from_le_bytes(len_buf);
+    // Checked before anything is allocated, so that a peer can't make us
+    // reserve memory just by claiming a long frame.
+    let too_long = || std::io::Error::new(std::io::ErrorKind::InvalidData, format!("frame over {max_len} bytes"));
+    if (len & !COMPRESSED) as usize > max_len {
+        return Err(too_long().into());
+    }
+    let mut buf = vec![0u8; (len & !COMPRESSED) as usize];
     r.read_exact(&mut buf).await?;
-    Ok(buf)
+    if len & COMPRESSED != 0 {
+        if compression != Compression::Zstd {
+            let msg = "compressed frame on a connection without compression";
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg).into());
+        }
+        // Bounded as an uncompressed frame is, however well the input packs.
+        let mut decoded = Vec::new();
+        zstd::Decoder::with_buffer(&buf[..])?.take(max_len as u64 + 1).read_to_end(&mut decoded)?;
+        if decoded.len() > max_len {
+            return Err(too_long().into());
+        }
+        buf = decoded;
+    }
+    Ok(Some(buf))
 }
 
-async fn write_postcard(w: &mut (impl AsyncWrite + Unpin), msg: &impl Serialize) -> Result<(), Error> {
-    write_frame(w, &postcard::to_allocvec(msg)?).await
+async fn write_postcard(w: &mut (impl AsyncWrite + Unpin), compression: Compression, msg: &impl Serialize) -> Result<(), Error> {
+    write_frame(w, compression, &postcard::to_allocvec(msg)?).await
 }
 
-async fn read_postcard<T: serde::de::DeserializeOwned>(r: &mut (impl AsyncRead + Unpin)) -> Result<T, Error> {
-    Ok(postcard::from_bytes(&read_frame(r).await?)?)
+async fn read_postcard<T: serde::de::DeserializeOwned>(r: &mut (impl AsyncRead + Unpin), compression: Compression) -> Result<T, Error> {
+    Ok(postcard::from_bytes(&read_frame(r, compression).await?)?)
 }
 
+// Columns are copied into one buffer rather than written from where they
+// lie: Arrow's IPC encoder copies each buffer to pad and align it anyway,
+// and a frame must be whole to be compressed.
 fn batch_to_ipc(batch: &RecordBatch) -> Result<Vec<u8>, Error> {
     let mut buf = Vec::new();
     let mut writer = StreamWriter::try_new(&mut buf, &batch.schema())?;
@@ -99,73 +438,422 @@ fn ipc_to_batch(bytes: &[u8]) -> Result<RecordBatch, Error> {
     Ok(batch)
 }
 
-async fn write_ipc(w: &mut (impl AsyncWrite + Unpin), batch: &RecordBatch) -> Result<(), Error> {
-    write_frame(w, &batch_to_ipc(batch)?).await
+fn schema_to_ipc(schema: &SchemaRef) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    StreamWriter::try_new(&mut buf, schema)?.finish()?;
+    Ok(buf)
+}
+
+fn ipc_to_schema(bytes: &[u8]) -> Result<SchemaRef, Error> {
+    Ok(StreamReader::try_new(std::io::Cursor::new(bytes), None)?.schema())
+}
+
+async fn write_ipc(w: &mut (impl AsyncWrite + Unpin), compression: Compression, batch: &RecordBatch) -> Result<(), Error> {
+    write_frame(w, compression, &batch_to_ipc(batch)?).await
 }
 
-async fn read_ipc(r: &mut (impl AsyncRead + Unpin)) -> Result<RecordBatch, Error> {
-    ipc_to_batch(&read_frame(r).await?)
+async fn read_ipc(r: &mut (impl AsyncRead + Unpin), compression: Compression) -> Result<RecordBatch, Error> {
+    ipc_to_batch(&read_frame(r, compression).await?)
 }
 
-pub async fn write_request(w: &mut (impl AsyncWrite + Unpin), req: &Request) -> Result<(), Error> {
+/// The namespace of requests that give none.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// Writes `req`, tagged with `id`, which the response will echo, for the
+/// database the server serves as `namespace`, or [`DEFAULT_NAMESPACE`].
+/// Large frames are compressed with `compression`, which must have been
+/// negotiated.
+pub async fn write_request(w: &mut (impl AsyncWrite + Unpin), id: u64, namespace: Option<&str>, req: &Request, compression: Compression) -> Result<(), Error> {
     match req {
         Request::JoinAsof { table, symbol, direction, timestamps } => {
-            write_postcard(w, &RequestHeader::JoinAsof {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::JoinAsof {
                 table: table.clone(),
                 symbol: symbol.clone(),
                 direction: *direction,
-            }).await?;
-            write_ipc(w, timestamps).await?;
+            })).await?;
+            write_ipc(w, compression, timestamps).await?;
         }
         Request::IngestBinance { market, day } => {
-            write_postcard(w, &RequestHeader::IngestBinance {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::IngestBinance {
                 market: *market,
                 day: *day,
-            }).await?;
+            })).await?;
+        }
+        Request::Auth { token } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Auth {
+                token: token.clone(),
+            })).await?;
+        }
+        Request::ListTables => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::ListTables)).await?;
+        }
+        Request::GetSchema { table } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::GetSchema {
+                table: table.clone(),
+            })).await?;
+        }
+        Request::DropTable { table } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::DropTable {
+                table: table.clone(),
+            })).await?;
+        }
+        Request::Range { table, symbol, start, end, columns } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Range {
+                table: table.clone(),
+                symbol: symbol.clone(),
+                start: *start,
+                end: *end,
+                columns: columns.clone(),
+            })).await?;
+        }
+        Request::Refresh => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Refresh)).await?;
+        }
+        Request::DropPartitionsBefore { table, day } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::DropPartitionsBefore {
+                table: table.clone(),
+                day: *day,
+            })).await?;
+        }
+        Request::Write { table, day, mode, durability, token, batches } => {
+            let chunks = u32::try_from(batches.len())
+                .ok()
+                .filter(|&chunks| chunks <= MAX_WRITE_CHUNKS)
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("write in over {MAX_WRITE_CHUNKS} batches")))?;
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Write {
+                table: table.clone(),
+                day: *day,
+                mode: *mode,
+                durability: *durability,
+                token: token.clone(),
+                chunks,
+            })).await?;
+            for batch in batches {
+                write_ipc(w, compression, batch).await?;
+            }
+        }
+        Request::ListPartitions => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::ListPartitions)).await?;
+        }
+        Request::GetPartition { table, day } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::GetPartition {
+                table: table.clone(),
+                day: *day,
+            })).await?;
+        }
+        Request::ReadPartitionFile { table, day, checksum, offset, len } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::ReadPartitionFile {
+                table: table.clone(),
+                day: *day,
+                checksum: *checksum,
+                offset: *offset,
+                len: *len,
+            })).await?;
+        }
+        Request::Subscribe { table, symbols } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Subscribe {
+                table: table.clone(),
+                symbols: symbols.clone(),
+            })).await?;
+        }
+        Request::Negotiate { compression: offered } => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Negotiate {
+                compression: offered.clone(),
+            })).await?;
+        }
+        Request::Drain => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Drain)).await?;
+        }
+        Request::Ping => {
+            write_postcard(w, compression, &(id, namespace, RequestHeader::Ping)).await?;
         }
     }
     w.flush().await?;
     Ok(())
 }
 
-pub async fn read_request(r: &mut (impl AsyncRead + Unpin)) -> Result<Request, Error> {
-    let header: RequestHeader = read_postcard(r).await?;
-    match header {
-        RequestHeader::JoinAsof { table, symbol, direction } => {
-            let timestamps = read_ipc(r).await?;
-            Ok(Request::JoinAsof { table, symbol, direction, timestamps })
+/// Bounds on the requests [`read_request`] reads, so that a client can't
+/// make the server allocate more than they allow.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// The longest frame, once decompressed.
+    pub max_frame_len: usize,
+    /// The most bytes a request's frames may add up to, once decompressed,
+    /// e.g. all the batches of a [`Request::Write`].
+    pub max_request_len: u64,
+}
+
+/// The most batches a [`Request::Write`] may be sent in.
+pub const MAX_WRITE_CHUNKS: u32 = 1 << 16;
+
+/// A request of which only the header has been read, so that the server can
+/// turn it away before reading the frames of its body.
+pub struct RequestHead {
+    pub id: u64,
+    pub namespace: Option<String>,
+    header: RequestHeader,
+    body: BodyReader,
+}
+
+/// What reading the frames of a request's body needs.
+struct BodyReader {
+    compression: Compression,
+    limits: Limits,
+    frame_timeout: Duration,
+    /// The bytes of the request read so far.
+    len: u64,
+}
+
+impl RequestHead {
+    /// Whether the request needs an authenticated connection on a server
+    /// with tokens; all but [`Request::Auth`], [`Request::Negotiate`] and
+    /// [`Request::Ping`] do.
+    pub fn needs_auth(&self) -> bool {
+        !matches!(self.header, RequestHeader::Auth { .. } | RequestHeader::Negotiate { .. } | RequestHeader::Ping)
+    }
+
+    /// Whether frames follow the header. A server that turns the request
+    /// away without reading them can't read further requests either.
+    pub fn has_body(&self) -> bool {
+        match self.header {
+            RequestHeader::JoinAsof { .. } => true,
+            RequestHeader::Write { chunks, .. } => chunks > 0,
+            _ => false,
         }
+    }
+
+    /// Reads the rest of the request, returning it with its ID and namespace.
+    pub async fn read_body(mut self, r: &mut (impl AsyncRead + Unpin)) -> Result<(u64, Option<String>, Request), Error> {
+        let request = match self.header {
+            RequestHeader::JoinAsof { table, symbol, direction } => {
+                let timestamps = ipc_to_batch(&self.body.read_frame(r).await?)?;
+                Request::JoinAsof { table, symbol, direction, timestamps }
+            }
+            RequestHeader::Write { table, day, mode, durability, token, chunks } => {
+                if chunks > MAX_WRITE_CHUNKS {
+                    let msg = format!("write in over {MAX_WRITE_CHUNKS} batches");
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg).into());
+                }
+                let mut batches = Vec::new();
+                for _ in 0..chunks {
+                    batches.push(ipc_to_batch(&self.body.read_frame(r).await?)?);
+                }
+                Request::Write { table, day, mode, durability, token, batches }
+            }
+            header => header_only(header),
+        };
+        Ok((self.id, self.namespace, request))
+    }
+}
+
+impl BodyReader {
+    /// Reads a frame of the body, adding its length to the request's.
+    async fn read_frame(&mut self, r: &mut (impl AsyncRead + Unpin)) -> Result<Vec<u8>, Error> {
+        let frame = read_frame_within(r, self.compression, &self.limits, self.frame_timeout)
+            .await?
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+        count_request_len(&mut self.len, frame.len(), &self.limits)?;
+        Ok(frame)
+    }
+}
+
+/// Reads the header of the next request, or `None` if the peer closed the
+/// connection cleanly between requests. `compression` is the one negotiated
+/// on the connection so far.
+///
+/// Each frame of the request, this one and those of its body, must arrive
+/// within `frame_timeout`, so that a large write sent in many frames has as
+/// long as it keeps sending them.
+pub async fn read_request(r: &mut (impl AsyncRead + Unpin), compression: Compression, limits: &Limits, frame_timeout: Duration) -> Result<Option<RequestHead>, Error> {
+    let Some(frame) = read_frame_within(r, compression, limits, frame_timeout).await? else {
+        return Ok(None);
+    };
+    let (id, namespace, header): (u64, Option<String>, RequestHeader) = postcard::from_bytes(&frame)?;
+    let mut len = 0;
+    count_request_len(&mut len, frame.len(), limits)?;
+    let body = BodyReader { compression, limits: *limits, frame_timeout, len };
+    Ok(Some(RequestHead { id, namespace, header, body }))
+}
+
+/// Reads a frame of a request, failing if it takes longer than `limit`.
+async fn read_frame_within(r: &mut (impl AsyncRead + Unpin), compression: Compression, limits: &Limits, limit: Duration) -> Result<Option<Vec<u8>>, Error> {
+    tokio::time::timeout(limit, read_frame_opt(r, compression, limits.max_frame_len))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out reading a request"))?
+}
+
+fn count_request_len(len: &mut u64, frame_len: usize, limits: &Limits) -> Result<(), Error> {
+    *len += frame_len as u64;
+    if *len > limits.max_request_len {
+        let msg = format!("request over {} bytes", limits.max_request_len);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg).into());
+    }
+    Ok(())
+}
+
+/// The request of a header that no frames follow.
+fn header_only(header: RequestHeader) -> Request {
+    match header {
+        RequestHeader::JoinAsof { .. } | RequestHeader::Write { .. } => unreachable!("the request has a body"),
         RequestHeader::IngestBinance { market, day } => {
-            Ok(Request::IngestBinance { market, day })
+            Request::IngestBinance { market, day }
+        }
+        RequestHeader::Auth { token } => Request::Auth { token },
+        RequestHeader::ListTables => Request::ListTables,
+        RequestHeader::GetSchema { table } => Request::GetSchema { table },
+        RequestHeader::DropTable { table } => Request::DropTable { table },
+        RequestHeader::Range { table, symbol, start, end, columns } => {
+            Request::Range { table, symbol, start, end, columns }
+        }
+        RequestHeader::Refresh => Request::Refresh,
+        RequestHeader::DropPartitionsBefore { table, day } => {
+            Request::DropPartitionsBefore { table, day }
+        }
+        RequestHeader::ListPartitions => Request::ListPartitions,
+        RequestHeader::GetPartition { table, day } => Request::GetPartition { table, day },
+        RequestHeader::ReadPartitionFile { table, day, checksum, offset, len } => {
+            Request::ReadPartitionFile { table, day, checksum, offset, len }
         }
+        RequestHeader::Subscribe { table, symbols } => Request::Subscribe { table, symbols },
+        RequestHeader::Negotiate { compression } => Request::Negotiate { compression },
+        RequestHeader::Drain => Request::Drain,
+        RequestHeader::Ping => Request::Ping,
     }
 }
 
-pub async fn write_response(w: &mut (impl AsyncWrite + Unpin), resp: &Response) -> Result<(), Error> {
+/// Writes `resp` to the request `id`, compressing large frames with
+/// `compression`, which must have been negotiated.
+pub async fn write_response(w: &mut (impl AsyncWrite + Unpin), id: u64, resp: &Response, compression: Compression) -> Result<(), Error> {
     match resp {
         Response::JoinAsof(batch) => {
-            write_postcard(w, &ResponseHeader::JoinAsof).await?;
-            write_ipc(w, batch).await?;
+            write_postcard(w, compression, &(id, ResponseHeader::JoinAsof)).await?;
+            write_ipc(w, compression, batch).await?;
         }
         Response::IngestBinance => {
-            write_postcard(w, &ResponseHeader::IngestBinance).await?;
+            write_postcard(w, compression, &(id, ResponseHeader::IngestBinance)).await?;
+        }
+        Response::Auth => {
+            write_postcard(w, compression, &(id, ResponseHeader::Auth)).await?;
+        }
+        Response::Unauthenticated => {
+            write_postcard(w, compression, &(id, ResponseHeader::Unauthenticated)).await?;
+        }
+        Response::ListTables(tables) => {
+            let headers = tables
+                .iter()
+                .map(|t| TableHeader { name: t.name.clone(), keys: t.keys.clone(), days: t.days })
+                .collect();
+            write_postcard(w, compression, &(id, ResponseHeader::ListTables(headers))).await?;
+            for table in tables {
+                write_frame(w, compression, &schema_to_ipc(&table.schema)?).await?;
+            }
+        }
+        Response::GetSchema(schema) => {
+            write_postcard(w, compression, &(id, ResponseHeader::GetSchema)).await?;
+            write_frame(w, compression, &schema_to_ipc(schema)?).await?;
+        }
+        Response::DropTable => {
+            write_postcard(w, compression, &(id, ResponseHeader::DropTable)).await?;
         }
-        Response::Error(msg) => {
-            write_postcard(w, &ResponseHeader::Error(msg.clone())).await?;
+        Response::Range(batch) => {
+            write_postcard(w, compression, &(id, ResponseHeader::Range)).await?;
+            write_ipc(w, compression, batch).await?;
+        }
+        Response::Refresh(added) => {
+            write_postcard(w, compression, &(id, ResponseHeader::Refresh(added.clone()))).await?;
+        }
+        Response::DropPartitionsBefore(days) => {
+            write_postcard(w, compression, &(id, ResponseHeader::DropPartitionsBefore(days.clone()))).await?;
+        }
+        Response::Write => {
+            write_postcard(w, compression, &(id, ResponseHeader::Write)).await?;
+        }
+        Response::ListPartitions(partitions) => {
+            write_postcard(w, compression, &(id, ResponseHeader::ListPartitions(partitions.clone()))).await?;
+        }
+        Response::GetPartition(batch) => {
+            write_postcard(w, compression, &(id, ResponseHeader::GetPartition)).await?;
+            write_ipc(w, compression, batch).await?;
+        }
+        Response::ReadPartitionFile(bytes) => {
+            write_postcard(w, compression, &(id, ResponseHeader::ReadPartitionFile)).await?;
+            write_frame(w, compression, bytes).await?;
+        }
+        Response::Subscribe => {
+            write_postcard(w, compression, &(id, ResponseHeader::Subscribe)).await?;
+        }
+        Response::Event(batch) => {
+            write_postcard(w, compression, &(id, ResponseHeader::Event)).await?;
+            write_ipc(w, compression, batch).await?;
+        }
+        Response::Negotiate(chosen) => {
+            write_postcard(w, compression, &(id, ResponseHeader::Negotiate(*chosen))).await?;
+        }
+        Response::Drain => {
+            write_postcard(w, compression, &(id, ResponseHeader::Drain)).await?;
+        }
+        Response::Ping(version) => {
+            write_postcard(w, compression, &(id, ResponseHeader::Ping(version.clone()))).await?;
+        }
+        Response::GoAway => {
+            write_postcard(w, compression, &(id, ResponseHeader::GoAway)).await?;
+        }
+        Response::Error(code, msg) => {
+            write_postcard(w, compression, &(id, ResponseHeader::Error(*code, msg.clone()))).await?;
         }
     }
     w.flush().await?;
     Ok(())
 }
 
-pub async fn read_response(r: &mut (impl AsyncRead + Unpin)) -> Result<Response, Error> {
-    let header: ResponseHeader = read_postcard(r).await?;
-    match header {
+/// Reads a response and the ID of the request it answers. `compression` is
+/// the one negotiated on the connection so far.
+pub async fn read_response(r: &mut (impl AsyncRead + Unpin), compression: Compression) -> Result<(u64, Response), Error> {
+    let (id, header): (u64, ResponseHeader) = read_postcard(r, compression).await?;
+    let response = match header {
         ResponseHeader::JoinAsof => {
-            let batch = read_ipc(r).await?;
-            Ok(Response::JoinAsof(batch))
+            let batch = read_ipc(r, compression).await?;
+            Response::JoinAsof(batch)
         }
-        ResponseHeader::IngestBinance => Ok(Response::IngestBinance),
-        ResponseHeader::Error(msg) => Ok(Response::Error(msg)),
-    }
+        ResponseHeader::IngestBinance => Response::IngestBinance,
+        ResponseHeader::Auth => Response::Auth,
+        ResponseHeader::Unauthenticated => Response::Unauthenticated,
+        ResponseHeader::ListTables(headers) => {
+            let mut tables = Vec::with_capacity(headers.len());
+            for TableHeader { name, keys, days } in headers {
+                let schema = ipc_to_schema(&read_frame(r, compression).await?)?;
+                tables.push(TableInfo { name, schema, keys, days });
+            }
+            Response::ListTables(tables)
+        }
+        ResponseHeader::GetSchema => {
+            let schema = ipc_to_schema(&read_frame(r, compression).await?)?;
+            Response::GetSchema(schema)
+        }
+        ResponseHeader::DropTable => Response::DropTable,
+        ResponseHeader::Range => {
+            let batch = read_ipc(r, compression).await?;
+            Response::Range(batch)
+        }
+        ResponseHeader::Refresh(added) => Response::Refresh(added),
+        ResponseHeader::DropPartitionsBefore(days) => Response::DropPartitionsBefore(days),
+        ResponseHeader::Write => Response::Write,
+        ResponseHeader::ListPartitions(partitions) => Response::ListPartitions(partitions),
+        ResponseHeader::GetPartition => {
+            let batch = read_ipc(r, compression).await?;
+            Response::GetPartition(batch)
+        }
+        ResponseHeader::ReadPartitionFile => Response::ReadPartitionFile(read_frame(r, compression).await?),
+        ResponseHeader::Subscribe => Response::Subscribe,
+        ResponseHeader::Event => {
+            let batch = read_ipc(r, compression).await?;
+            Response::Event(batch)
+        }
+        ResponseHeader::Negotiate(chosen) => Response::Negotiate(chosen),
+        ResponseHeader::Drain => Response::Drain,
+        ResponseHeader::Ping(version) => Response::Ping(version),
+        ResponseHeader::GoAway => Response::GoAway,
+        ResponseHeader::Error(code, msg) => Response::Error(code, msg),
+    };
+    Ok((id, response))
 }
@@ -0,0 +1,372 @@
+//! Python bindings: `zola_db.Db` opens a database in process and
+//! `zola_db.Client` talks to a server. Both take timestamps, in microseconds,
+//! and value columns as numpy arrays, and return query results as a dict of
+//! numpy arrays by column name.
+
+use std::fmt::Display;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, AsArray, BooleanArray, Float32Array, Float64Array, Int32Array, Int64Array,
+    StringArray,
+};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Float32Type, Float64Type, Int32Type, Int64Type, Schema};
+use arrow::record_batch::RecordBatch;
+use numpy::{Element, PyArray1, PyReadonlyArray1};
+use pyo3::IntoPyObjectExt;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use zola_db::{Direction, SYMBOL_COL, TIMESTAMP_COL, WriteMode};
+
+create_exception!(
+    zola_db,
+    Error,
+    PyException,
+    "An error reported by the database."
+);
+
+fn error(e: impl Display) -> PyErr {
+    Error::new_err(e.to_string())
+}
+
+fn direction(direction: &str) -> PyResult<Direction> {
+    match direction {
+        "backward" => Ok(Direction::Backward),
+        "forward" => Ok(Direction::Forward),
+        _ => Err(PyValueError::new_err(format!(
+            "direction must be \"backward\" or \"forward\", not {direction:?}"
+        ))),
+    }
+}
+
+fn write_mode(mode: &str) -> PyResult<WriteMode> {
+    match mode {
+        "error" => Ok(WriteMode::ErrorIfExists),
+        "overwrite" => Ok(WriteMode::Overwrite),
+        "append" => Ok(WriteMode::Append),
+        _ => Err(PyValueError::new_err(format!(
+            "mode must be \"error\", \"overwrite\" or \"append\", not {mode:?}"
+        ))),
+    }
+}
+
+/// The probe batch of an as-of join.
+fn probes(timestamps: PyReadonlyArray1<i64>) -> PyResult<RecordBatch> {
+    let timestamps = Int64Array::from_iter_values(timestamps.as_array().iter().copied());
+    let schema = Schema::new(vec![Field::new(TIMESTAMP_COL, DataType::Int64, false)]);
+    RecordBatch::try_new(Arc::new(schema), vec![Arc::new(timestamps)]).map_err(error)
+}
+
+/// Builds the rows of a write from a symbol per row, their timestamps and a
+/// dict of value columns, in the order the table's columns are in.
+fn rows(
+    symbols: Vec<String>,
+    timestamps: PyReadonlyArray1<i64>,
+    columns: &Bound<PyDict>,
+) -> PyResult<RecordBatch> {
+    let symbol_type = DataType::RunEndEncoded(
+        Arc::new(Field::new("run_ends", DataType::Int32, false)),
+        Arc::new(Field::new("values", DataType::Utf8, true)),
+    );
+    let symbols = cast(&StringArray::from(symbols), &symbol_type).map_err(error)?;
+    let mut fields = vec![
+        Field::new(SYMBOL_COL, symbol_type, false),
+        Field::new(TIMESTAMP_COL, DataType::Int64, false),
+    ];
+    let mut arrays = vec![
+        symbols,
+        Arc::new(Int64Array::from_iter_values(
+            timestamps.as_array().iter().copied(),
+        )) as ArrayRef,
+    ];
+    for (name, values) in columns {
+        let name: String = name.extract()?;
+        let values = column(&name, &values)?;
+        fields.push(Field::new(name, values.data_type().clone(), false));
+        arrays.push(values);
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn column(name: &str, values: &Bound<PyAny>) -> PyResult<ArrayRef> {
+    fn extract<T: Element + Copy>(values: &Bound<PyAny>) -> Option<Vec<T>> {
+        let values = values.extract::<PyReadonlyArray1<T>>().ok()?;
+        Some(values.as_array().iter().copied().collect())
+    }
+    if let Some(values) = extract::<f64>(values) {
+        return Ok(Arc::new(Float64Array::from(values)));
+    }
+    if let Some(values) = extract::<f32>(values) {
+        return Ok(Arc::new(Float32Array::from(values)));
+    }
+    if let Some(values) = extract::<i64>(values) {
+        return Ok(Arc::new(Int64Array::from(values)));
+    }
+    if let Some(values) = extract::<i32>(values) {
+        return Ok(Arc::new(Int32Array::from(values)));
+    }
+    if let Some(values) = extract::<bool>(values) {
+        return Ok(Arc::new(BooleanArray::from(values)));
+    }
+    Err(PyTypeError::new_err(format!(
+        "column {name:?} must be a 1-d numpy array of float64, float32, int64, int32 or bool"
+    )))
+}
+
+/// Converts `batch` to a dict of numpy arrays by column name. Strings become
+/// object arrays, and numeric columns with nulls become float64 with NaN for
+/// each null.
+fn to_numpy<'py>(py: Python<'py>, batch: &RecordBatch) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        dict.set_item(field.name(), to_numpy_array(py, column)?)?;
+    }
+    Ok(dict)
+}
+
+fn to_numpy_array<'py>(py: Python<'py>, column: &ArrayRef) -> PyResult<Bound<'py, PyAny>> {
+    match column.data_type() {
+        DataType::Utf8 | DataType::RunEndEncoded(..) => {
+            let strings = cast(column, &DataType::Utf8).map_err(error)?;
+            let strings = (strings.as_string::<i32>().iter())
+                .map(|s| s.into_py_any(py))
+                .collect::<PyResult<Vec<_>>>()?;
+            Ok(PyArray1::from_vec(py, strings).into_any())
+        }
+        DataType::Float64
+        | DataType::Float32
+        | DataType::Int64
+        | DataType::Int32
+        | DataType::Boolean
+            if column.null_count() > 0 =>
+        {
+            let values = cast(column, &DataType::Float64).map_err(error)?;
+            let values = values.as_primitive::<Float64Type>();
+            let values = (values.iter()).map(|v| v.unwrap_or(f64::NAN)).collect();
+            Ok(PyArray1::from_vec(py, values).into_any())
+        }
+        DataType::Float64 => {
+            Ok(PyArray1::from_slice(py, column.as_primitive::<Float64Type>().values()).into_any())
+        }
+        DataType::Float32 => {
+            Ok(PyArray1::from_slice(py, column.as_primitive::<Float32Type>().values()).into_any())
+        }
+        DataType::Int64 => {
+            Ok(PyArray1::from_slice(py, column.as_primitive::<Int64Type>().values()).into_any())
+        }
+        DataType::Int32 => {
+            Ok(PyArray1::from_slice(py, column.as_primitive::<Int32Type>().values()).into_any())
+        }
+        DataType::Boolean => {
+            let values = column.as_boolean().values().iter().collect();
+            Ok(PyArray1::from_vec(py, values).into_any())
+        }
+        other => Err(PyTypeError::new_err(format!(
+            "columns of type {other} cannot be converted to numpy"
+        ))),
+    }
+}
+
+/// A database opened in this process.
+#[pyclass(frozen)]
+struct Db {
+    db: zola_db::Db,
+}
+
+#[pymethods]
+impl Db {
+    /// Opens the database in the directory `path`, or an empty one held in
+    /// memory if `path` is None.
+    #[new]
+    #[pyo3(signature = (path=None))]
+    fn new(path: Option<std::path::PathBuf>) -> PyResult<Self> {
+        let db = match path {
+            Some(path) => zola_db::Db::open(path).map_err(error)?,
+            None => zola_db::Db::open_in_memory(),
+        };
+        Ok(Db { db })
+    }
+
+    /// Stores rows of `table` for `day`: a symbol and a timestamp per row,
+    /// grouped by symbol with timestamps sorted within each, and a dict of
+    /// value columns. `mode` is "error", "overwrite" or "append".
+    #[pyo3(signature = (table, day, symbols, timestamps, columns, mode="append"))]
+    fn write(
+        &self,
+        table: &str,
+        day: jiff::civil::Date,
+        symbols: Vec<String>,
+        timestamps: PyReadonlyArray1<i64>,
+        columns: &Bound<PyDict>,
+        mode: &str,
+    ) -> PyResult<()> {
+        let mode = write_mode(mode)?;
+        let batch = rows(symbols, timestamps, columns)?;
+        columns
+            .py()
+            .detach(|| self.db.ingest(table, day.into(), batch, mode))
+            .map_err(error)?;
+        Ok(())
+    }
+
+    /// For each of `timestamps`, the row of `symbol` in `table` at or before
+    /// it, or at or after it if `direction` is "forward".
+    #[pyo3(signature = (table, symbol, timestamps, direction="backward"))]
+    fn asof<'py>(
+        &self,
+        py: Python<'py>,
+        table: &str,
+        symbol: &str,
+        timestamps: PyReadonlyArray1<i64>,
+        direction: &str,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let direction = self::direction(direction)?;
+        let probes = probes(timestamps)?;
+        let batch = py
+            .detach(|| self.db.join_asof(table, symbol, &probes, direction))
+            .map_err(error)?;
+        to_numpy(py, &batch)
+    }
+
+    /// The rows of `symbol` in `table` with timestamps in `[start, end)`, with
+    /// only `columns` if given.
+    #[pyo3(signature = (table, symbol, start, end, columns=None))]
+    fn range<'py>(
+        &self,
+        py: Python<'py>,
+        table: &str,
+        symbol: &str,
+        start: i64,
+        end: i64,
+        columns: Option<Vec<String>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let columns: Option<Vec<&str>> =
+            (columns.as_ref()).map(|columns| columns.iter().map(String::as_str).collect());
+        let batch = py
+            .detach(|| self.db.range(table, symbol, start..end, columns.as_deref()))
+            .map_err(error)?;
+        to_numpy(py, &batch)
+    }
+
+    /// The names of the tables, in order.
+    fn tables(&self) -> Vec<String> {
+        self.db
+            .tables()
+            .into_iter()
+            .map(|table| table.name)
+            .collect()
+    }
+}
+
+/// A connection to a server, with the methods of `Db`.
+#[pyclass(frozen)]
+struct Client {
+    client: zola_db_client::Client,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl Client {
+    fn block_on<T: Send>(
+        &self,
+        py: Python,
+        future: impl Future<Output = Result<T, zola_db_client::Error>> + Send,
+    ) -> PyResult<T> {
+        py.detach(|| self.runtime.block_on(future)).map_err(error)
+    }
+}
+
+#[pymethods]
+impl Client {
+    /// Connects to the server at `addr` as needed, authenticating with
+    /// `token` and working in `namespace` if given.
+    #[new]
+    #[pyo3(signature = (addr, token=None, namespace=None))]
+    fn new(addr: String, token: Option<String>, namespace: Option<String>) -> PyResult<Self> {
+        let mut builder = zola_db_client::Client::builder();
+        if let Some(token) = token {
+            builder = builder.token(token);
+        }
+        if let Some(namespace) = namespace {
+            builder = builder.namespace(namespace);
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Client {
+            client: builder.build(addr),
+            runtime,
+        })
+    }
+
+    /// Like `Db.write`.
+    #[pyo3(signature = (table, day, symbols, timestamps, columns, mode="append"))]
+    fn write(
+        &self,
+        table: &str,
+        day: jiff::civil::Date,
+        symbols: Vec<String>,
+        timestamps: PyReadonlyArray1<i64>,
+        columns: &Bound<PyDict>,
+        mode: &str,
+    ) -> PyResult<()> {
+        let mode = write_mode(mode)?;
+        let batch = rows(symbols, timestamps, columns)?;
+        self.block_on(columns.py(), self.client.write(table, day, &batch, mode))
+    }
+
+    /// Like `Db.asof`.
+    #[pyo3(signature = (table, symbol, timestamps, direction="backward"))]
+    fn asof<'py>(
+        &self,
+        py: Python<'py>,
+        table: &str,
+        symbol: &str,
+        timestamps: PyReadonlyArray1<i64>,
+        direction: &str,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let direction = self::direction(direction)?;
+        let probes = probes(timestamps)?;
+        let batch = self.block_on(py, self.client.join_asof(table, symbol, &probes, direction))?;
+        to_numpy(py, &batch)
+    }
+
+    /// Like `Db.range`.
+    #[pyo3(signature = (table, symbol, start, end, columns=None))]
+    fn range<'py>(
+        &self,
+        py: Python<'py>,
+        table: &str,
+        symbol: &str,
+        start: i64,
+        end: i64,
+        columns: Option<Vec<String>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let columns: Option<Vec<&str>> =
+            (columns.as_ref()).map(|columns| columns.iter().map(String::as_str).collect());
+        let batch = self.block_on(
+            py,
+            self.client
+                .range(table, symbol, start..end, columns.as_deref()),
+        )?;
+        to_numpy(py, &batch)
+    }
+
+    /// Like `Db.tables`, listing those this client may read.
+    fn tables(&self, py: Python) -> PyResult<Vec<String>> {
+        let tables = self.block_on(py, self.client.list_tables())?;
+        Ok(tables.into_iter().map(|table| table.name).collect())
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "zola_db")]
+fn init(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<Db>()?;
+    m.add_class::<Client>()?;
+    m.add("Error", m.py().get_type::<Error>())?;
+    Ok(())
+}
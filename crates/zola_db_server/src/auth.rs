@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use zola_db_proto::DEFAULT_NAMESPACE;
+
+/// What a grant allows. Each permission implies those before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Read,
+    Write,
+    /// Maintenance such as retention; server-wide operations like refresh
+    /// need it for all tables.
+    Admin,
+}
+
+/// The tables a grant covers: `*` for all, `<prefix>*` for those starting with
+/// the prefix, or one table by name.
+#[derive(Debug)]
+enum Tables {
+    All,
+    Prefix(String),
+    Exact(String),
+}
+
+impl Tables {
+    fn parse(pattern: &str) -> Self {
+        match pattern.strip_suffix('*') {
+            Some("") => Tables::All,
+            Some(prefix) => Tables::Prefix(prefix.to_string()),
+            None => Tables::Exact(pattern.to_string()),
+        }
+    }
+
+    fn matches(&self, table: &str) -> bool {
+        match self {
+            Tables::All => true,
+            Tables::Prefix(prefix) => table.starts_with(prefix.as_str()),
+            Tables::Exact(name) => table == name,
+        }
+    }
+}
+
+/// One grant of a token: `permission` on `tables` of `namespace`, or of
+/// every namespace if `None`.
+#[derive(Debug)]
+struct Grant {
+    permission: Permission,
+    namespace: Option<String>,
+    tables: Tables,
+}
+
+impl Grant {
+    fn gives(&self, namespace: &str, permission: Permission) -> bool {
+        self.permission >= permission && self.namespace.as_deref().is_none_or(|ns| ns == namespace)
+    }
+}
+
+/// The grants of one token.
+#[derive(Debug, Default)]
+pub struct Acl(Vec<Grant>);
+
+impl Acl {
+    pub fn permits(&self, namespace: &str, table: &str, permission: Permission) -> bool {
+        self.0
+            .iter()
+            .any(|grant| grant.gives(namespace, permission) && grant.tables.matches(table))
+    }
+
+    /// Whether a single grant gives `permission` on every table of
+    /// `namespace`.
+    pub fn permits_all(&self, namespace: &str, permission: Permission) -> bool {
+        self.0
+            .iter()
+            .any(|grant| grant.gives(namespace, permission) && matches!(grant.tables, Tables::All))
+    }
+}
+
+/// The server's tokens, loaded from a file with one
+/// `<token> <read|write|admin> [[namespace:]tables]` grant per line, where
+/// `tables` is a pattern as for [`Tables`] and defaults to `*`. The grant is
+/// for tables of `namespace`, or of every namespace if it is `*`, and
+/// defaults to the default namespace. A token may have several grants. Blank
+/// lines and lines starting with `#` are ignored.
+pub struct Tokens(HashMap<String, Acl>);
+
+impl Tokens {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut tokens: HashMap<String, Acl> = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: &str| format!("{}:{}: {msg}", path.display(), i + 1);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (token, permission, tables) = match words[..] {
+                [token, permission] => (token, permission, "*"),
+                [token, permission, tables] => (token, permission, tables),
+                _ => {
+                    return Err(err(
+                        "expected `<token> <read|write|admin> [[namespace:]tables]`",
+                    ));
+                }
+            };
+            let permission = match permission {
+                "read" => Permission::Read,
+                "write" => Permission::Write,
+                "admin" => Permission::Admin,
+                _ => return Err(err("permission must be `read`, `write` or `admin`")),
+            };
+            let (namespace, tables) = match tables.split_once(':') {
+                Some(("*", tables)) => (None, tables),
+                Some((namespace, tables)) => (Some(namespace.to_string()), tables),
+                None => (Some(DEFAULT_NAMESPACE.to_string()), tables),
+            };
+            let acl = tokens.entry(token.to_string()).or_default();
+            acl.0.push(Grant {
+                permission,
+                namespace,
+                tables: Tables::parse(tables),
+            });
+        }
+        Ok(Self(tokens))
+    }
+
+    pub fn get(&self, token: &str) -> Option<&Acl> {
+        self.0.get(token)
+    }
+}
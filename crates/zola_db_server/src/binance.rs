@@ -50,10 +50,10 @@ pub async fn list_symbols(client: &Client, market: Market) -> Result<Vec<String>
         for part in body.split("<Prefix>").skip(1) {
             if let Some(end) = part.find("</Prefix>") {
                 let p = &part[..end];
-                if let Some(sym) = p.strip_prefix(prefix).and_then(|s| s.strip_suffix('/')) {
-                    if !sym.is_empty() {
-                        symbols.push(sym.to_string());
-                    }
+                if let Some(sym) = p.strip_prefix(prefix).and_then(|s| s.strip_suffix('/'))
+                    && !sym.is_empty()
+                {
+                    symbols.push(sym.to_string());
                 }
             }
         }
@@ -207,10 +207,10 @@ pub async fn fetch(client: &Client, market: Market, symbols: &[String], day: jif
     // complete data across all symbols to form a valid partition.
     let mut symbol_data = Vec::new();
     while let Some(result) = join_set.join_next().await {
-        if let Some((sym, data)) = result?? {
-            if !data.timestamps.is_empty() {
-                symbol_data.push((sym, data));
-            }
+        if let Some((sym, data)) = result??
+            && !data.timestamps.is_empty()
+        {
+            symbol_data.push((sym, data));
         }
     }
 
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use tokio::sync::oneshot;
+use zola_db::{Db, Durability, EpochDay, WriteMode};
+use zola_db_proto::ErrorCode;
+
+use crate::{Failure, failure};
+
+/// Merges appends to the same table and day that arrive within a window of
+/// each other into one [`Db::ingest_days`] call, so a burst of small writes
+/// rewrites the partition once rather than once per write.
+///
+/// If the `Db` rejects the group, e.g. for one batch with unsorted rows, its
+/// writes are retried one by one, so that only the bad ones fail. The group is
+/// synced to disk if any of its writes asks for [`Durability::Always`].
+pub struct Coalescer {
+    db: Arc<Db>,
+    window: Duration,
+    pending: Mutex<HashMap<(String, EpochDay), Group>>,
+}
+
+struct Group {
+    batches: Vec<RecordBatch>,
+    durability: Durability,
+    waiters: Vec<oneshot::Sender<Result<(), Failure>>>,
+}
+
+impl Coalescer {
+    pub fn new(db: Arc<Db>, window: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            db,
+            window,
+            pending: Mutex::default(),
+        })
+    }
+
+    /// Appends `batch` to `table` for `day`, together with the other appends
+    /// to it within the window, and returns once they are written.
+    pub async fn append(
+        self: &Arc<Self>,
+        table: String,
+        day: EpochDay,
+        batch: RecordBatch,
+        durability: Durability,
+    ) -> Result<(), Failure> {
+        // Reject a mismatched batch here rather than fail its group with it.
+        if let Ok(schema) = self.db.schema(&table)
+            && schema.fields() != batch.schema().fields()
+        {
+            let msg = format!(
+                "expected schema {:?}, got {:?}",
+                schema.fields(),
+                batch.schema().fields(),
+            );
+            let msg = ArrowError::SchemaError(msg).to_string();
+            return Err((ErrorCode::InvalidInput, msg));
+        }
+        let (tx, rx) = oneshot::channel();
+        let key = (table, day);
+        let first = {
+            let mut pending = self.pending.lock().unwrap();
+            let group = pending.entry(key.clone()).or_insert_with(|| Group {
+                batches: Vec::new(),
+                durability: Durability::Never,
+                waiters: Vec::new(),
+            });
+            group.batches.push(batch);
+            if durability == Durability::Always {
+                group.durability = Durability::Always;
+            }
+            group.waiters.push(tx);
+            group.waiters.len() == 1
+        };
+        if first {
+            let this = Arc::clone(self);
+            tokio::spawn(async move { this.flush(key).await });
+        }
+        rx.await
+            .map_err(|_| (ErrorCode::Internal, "write abandoned".to_string()))?
+    }
+
+    async fn flush(&self, key: (String, EpochDay)) {
+        tokio::time::sleep(self.window).await;
+        let group = self.pending.lock().unwrap().remove(&key).unwrap();
+        let db = Arc::clone(&self.db);
+        let (table, day) = key;
+        let (batches, durability) = (group.batches, group.durability);
+        let count = batches.len();
+        let results = tokio::task::spawn_blocking(move || {
+            let ingest = |batches: Vec<RecordBatch>| {
+                let batches = batches.into_iter().map(|b| (day, b)).collect();
+                db.ingest_days_with(&table, batches, WriteMode::Append, durability)
+                    .map(drop)
+                    .map_err(failure)
+            };
+            match ingest(batches.clone()) {
+                // A failed group wrote nothing, so each write can be retried.
+                Err(_) if count > 1 => batches.into_iter().map(|b| ingest(vec![b])).collect(),
+                result => vec![result; count],
+            }
+        })
+        .await
+        .unwrap_or_else(|e| vec![Err((ErrorCode::Internal, e.to_string())); count]);
+        for (waiter, result) in group.waiters.into_iter().zip(results) {
+            let _ = waiter.send(result);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use zola_db::Durability;
+use zola_db_proto::{DEFAULT_NAMESPACE, Limits};
+
+use crate::listen::BindAddr;
+
+pub const USAGE: &str = "usage: zola_db_server [--config <path>] [--data-dir <path>] \
+    [--bind <addr>[,<addr>...]] [--ws-bind <addr>] [--tokens <path>] [--max-connections <n>] [--max-frame-len <bytes>] \
+    [--max-request-len <bytes>] [--read-timeout <secs>] \
+    [--write-timeout <secs>] [--idle-timeout <secs>] [--shutdown-timeout <secs>] \
+    [--write-coalesce-ms <ms>] [--min-durability always|never] [--replica-of <addr>] [--replica-token <token>] \
+    [--namespace.<name> <path>]...";
+
+/// Server settings, from a config file and command-line flags.
+///
+/// The file holds `key = value` lines, where values are double-quoted strings
+/// or integers — the flat subset of TOML:
+///
+/// ```toml
+/// data_dir = "/var/lib/zola_db"
+/// bind = "0.0.0.0:9867, [::]:9867, unix:/run/zola_db.sock"
+/// ws_bind = "0.0.0.0:9868"
+/// tokens = "/etc/zola_db/tokens"
+/// max_connections = 1024
+/// max_frame_len = 268435456      # 256 MiB
+/// max_request_len = 1073741824   # 1 GiB
+/// read_timeout = 30   # seconds
+/// write_timeout = 30
+/// idle_timeout = 300
+/// shutdown_timeout = 30
+/// write_coalesce_ms = 5
+/// min_durability = "always"
+/// replica_of = "primary.example:9867"
+/// replica_token = "secret"
+/// namespace.research = "/var/lib/zola_db_research"
+/// ```
+///
+/// Each key can also be given as a flag, e.g. `--data-dir`, which overrides
+/// the file. Only `data_dir` is required.
+///
+/// `bind` lists the addresses to listen on, each an IPv4 or IPv6 socket
+/// address or `unix:` and the path of a Unix domain socket.
+///
+/// `data_dir` is the data root of the default namespace, and each
+/// `namespace.<name>` key adds another that requests can select by name.
+#[derive(Debug)]
+pub struct Config {
+    pub data_dir: PathBuf,
+    pub bind: Vec<BindAddr>,
+    /// Where to serve the WebSocket live tail of [`crate::tail`], if at all.
+    pub ws_bind: Option<SocketAddr>,
+    /// The tokens file for [`crate::auth::Tokens`]; `None` disables
+    /// authentication.
+    pub tokens: Option<PathBuf>,
+    pub max_connections: usize,
+    /// How large a request, and each of its frames, may be.
+    pub limits: Limits,
+    pub timeouts: Timeouts,
+    /// How long to hold appends to a table and day for others to merge with,
+    /// as [`crate::coalesce::Coalescer`] does; `None` writes each at once.
+    pub write_coalesce: Option<Duration>,
+    /// The least durability a write gets, whatever the client asks for:
+    /// `"always"` syncs every write to disk, while the default, `"never"`,
+    /// lets each write choose.
+    pub min_durability: Durability,
+    /// The address of the primary to follow as a read-only replica, as
+    /// [`crate::replica::follow`] does.
+    pub replica_of: Option<String>,
+    /// The token to authenticate to the primary with. Only the tables it may
+    /// read are replicated.
+    pub replica_token: Option<String>,
+    /// The data roots of namespaces other than the default, by name.
+    pub namespaces: Vec<(String, PathBuf)>,
+}
+
+#[derive(Debug)]
+pub struct Timeouts {
+    /// For each frame of a request once its first byte has arrived, so a
+    /// write sent in chunks has as long as it keeps them coming.
+    pub read: Duration,
+    /// For sending a response.
+    pub write: Duration,
+    /// Between requests. Expiry closes the connection without an error, so
+    /// clients can hold connections open as long as they keep using them.
+    pub idle: Duration,
+    /// For in-flight requests to finish once SIGTERM, SIGINT or a drain
+    /// request arrives.
+    pub shutdown: Duration,
+}
+
+enum Value {
+    String(String),
+    Integer(u64),
+}
+
+impl Config {
+    /// Parses the command line, reading the config file it names, if any.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut file = None;
+        let mut flags = Vec::new();
+        while let Some(arg) = args.next() {
+            let Some(key) = arg.strip_prefix("--") else {
+                return Err(format!("unexpected argument {arg:?}"));
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {arg}"))?;
+            match key {
+                "config" => file = Some(PathBuf::from(value)),
+                // Namespace names are kept as given.
+                _ => {
+                    let key = match key.split_once('.') {
+                        Some((key, name)) => format!("{}.{name}", key.replace('-', "_")),
+                        None => key.replace('-', "_"),
+                    };
+                    flags.push((key, value))
+                }
+            }
+        }
+
+        let mut builder = Builder::default();
+        if let Some(path) = file {
+            let text =
+                std::fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            for (i, line) in text.lines().enumerate() {
+                let err = |msg: String| format!("{}:{}: {msg}", path.display(), i + 1);
+                if let Some((key, value)) = parse_line(line).map_err(err)? {
+                    builder.set(key, value).map_err(err)?;
+                }
+            }
+        }
+        for (key, value) in flags {
+            let value = match value.parse() {
+                Ok(n) if Builder::is_integer(&key) => Value::Integer(n),
+                _ => Value::String(value),
+            };
+            builder
+                .set(&key, value)
+                .map_err(|e| format!("--{}: {e}", key.replace('_', "-")))?;
+        }
+        builder.build()
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    data_dir: Option<PathBuf>,
+    bind: Option<Vec<BindAddr>>,
+    ws_bind: Option<SocketAddr>,
+    tokens: Option<PathBuf>,
+    max_connections: Option<usize>,
+    max_frame_len: Option<usize>,
+    max_request_len: Option<u64>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    shutdown_timeout: Option<Duration>,
+    write_coalesce: Option<Duration>,
+    min_durability: Option<Durability>,
+    replica_of: Option<String>,
+    replica_token: Option<String>,
+    namespaces: Vec<(String, PathBuf)>,
+}
+
+impl Builder {
+    fn is_integer(key: &str) -> bool {
+        matches!(
+            key,
+            "max_connections"
+                | "max_frame_len"
+                | "max_request_len"
+                | "read_timeout"
+                | "write_timeout"
+                | "idle_timeout"
+                | "shutdown_timeout"
+                | "write_coalesce_ms"
+        )
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match (key, value) {
+            ("data_dir", Value::String(s)) => self.data_dir = Some(s.into()),
+            ("bind", Value::String(s)) => {
+                let addrs = s.split(',').map(|addr| addr.trim().parse());
+                self.bind = Some(addrs.collect::<Result<_, _>>()?);
+            }
+            ("ws_bind", Value::String(s)) => {
+                let addr = s.parse().map_err(|_| format!("invalid address {s:?}"))?;
+                self.ws_bind = Some(addr);
+            }
+            ("tokens", Value::String(s)) => self.tokens = Some(s.into()),
+            ("min_durability", Value::String(s)) => {
+                self.min_durability = Some(match s.as_str() {
+                    "always" => Durability::Always,
+                    "never" => Durability::Never,
+                    _ => return Err(format!("invalid durability {s:?}")),
+                });
+            }
+            ("replica_of", Value::String(s)) => self.replica_of = Some(s),
+            ("replica_token", Value::String(s)) => self.replica_token = Some(s),
+            (key, value) if let Some(name) = key.strip_prefix("namespace.") => {
+                let Value::String(s) = value else {
+                    return Err(format!("{key} must be a string"));
+                };
+                if name.is_empty() || name == DEFAULT_NAMESPACE {
+                    return Err(format!("invalid namespace name {name:?}"));
+                }
+                self.namespaces.retain(|(n, _)| n != name);
+                self.namespaces.push((name.to_string(), s.into()));
+            }
+            ("max_connections", Value::Integer(0)) => {
+                return Err("max_connections must be positive".into());
+            }
+            ("max_connections", Value::Integer(n)) => self.max_connections = Some(n as usize),
+            ("max_frame_len", Value::Integer(n)) if n == 0 || n >= 1 << 31 => {
+                return Err("max_frame_len must be positive and under 2 GiB".into());
+            }
+            ("max_frame_len", Value::Integer(n)) => self.max_frame_len = Some(n as usize),
+            ("max_request_len", Value::Integer(0)) => {
+                return Err("max_request_len must be positive".into());
+            }
+            ("max_request_len", Value::Integer(n)) => self.max_request_len = Some(n),
+            ("read_timeout", Value::Integer(n)) => self.read_timeout = Some(Duration::from_secs(n)),
+            ("write_timeout", Value::Integer(n)) => {
+                self.write_timeout = Some(Duration::from_secs(n))
+            }
+            ("idle_timeout", Value::Integer(n)) => self.idle_timeout = Some(Duration::from_secs(n)),
+            ("shutdown_timeout", Value::Integer(n)) => {
+                self.shutdown_timeout = Some(Duration::from_secs(n))
+            }
+            ("write_coalesce_ms", Value::Integer(n)) => {
+                self.write_coalesce = Some(Duration::from_millis(n))
+            }
+            (
+                "data_dir" | "bind" | "ws_bind" | "tokens" | "min_durability" | "replica_of"
+                | "replica_token",
+                _,
+            ) => {
+                return Err(format!("{key} must be a string"));
+            }
+            (key, _) if Self::is_integer(key) => {
+                return Err(format!("{key} must be a non-negative integer"));
+            }
+            (key, _) => return Err(format!("unknown setting {key:?}")),
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<Config, String> {
+        Ok(Config {
+            data_dir: self
+                .data_dir
+                .ok_or_else(|| "data_dir is required".to_string())?,
+            bind: self
+                .bind
+                .unwrap_or_else(|| vec![BindAddr::Tcp(([127, 0, 0, 1], 9867).into())]),
+            ws_bind: self.ws_bind,
+            tokens: self.tokens,
+            max_connections: self.max_connections.unwrap_or(1024),
+            limits: Limits {
+                max_frame_len: self.max_frame_len.unwrap_or(256 << 20),
+                max_request_len: self.max_request_len.unwrap_or(1 << 30),
+            },
+            timeouts: Timeouts {
+                read: self.read_timeout.unwrap_or(Duration::from_secs(30)),
+                write: self.write_timeout.unwrap_or(Duration::from_secs(30)),
+                idle: self.idle_timeout.unwrap_or(Duration::from_secs(300)),
+                shutdown: self.shutdown_timeout.unwrap_or(Duration::from_secs(30)),
+            },
+            write_coalesce: self.write_coalesce.filter(|window| !window.is_zero()),
+            min_durability: self.min_durability.unwrap_or(Durability::Never),
+            replica_of: self.replica_of,
+            replica_token: self.replica_token,
+            namespaces: self.namespaces,
+        })
+    }
+}
+
+/// Parses one line of a config file, or `None` if it is blank or a comment.
+fn parse_line(line: &str) -> Result<Option<(&str, Value)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (key, rest) = line
+        .split_once('=')
+        .ok_or_else(|| "expected `key = value`".to_string())?;
+    let key = key.trim();
+    let rest = rest.trim();
+    let (value, rest) = if let Some(quoted) = rest.strip_prefix('"') {
+        let end = quoted
+            .find('"')
+            .ok_or_else(|| "unterminated string".to_string())?;
+        if quoted[..end].contains('\\') {
+            return Err("escapes in strings are not supported".into());
+        }
+        (Value::String(quoted[..end].to_string()), &quoted[end + 1..])
+    } else {
+        let end = rest.find([' ', '\t', '#']).unwrap_or(rest.len());
+        let n = rest[..end]
+            .parse()
+            .map_err(|_| format!("invalid value {:?}", &rest[..end]))?;
+        (Value::Integer(n), &rest[end..])
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err("unexpected text after value".into());
+    }
+    Ok(Some((key, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Parses `flags` after `--config` naming a file holding `file`.
+    fn parse(file: &str, flags: &[&str]) -> Result<Config, String> {
+        parse_around(&[], file, flags)
+    }
+
+    /// Parses `before`, then `--config` naming a file holding `file`, then
+    /// `after`.
+    fn parse_around(before: &[&str], file: &str, after: &[&str]) -> Result<Config, String> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "zola_db_config_{}_{}.toml",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, file).unwrap();
+        let config = ["--config", path.to_str().unwrap()];
+        let args = before.iter().chain(&config).chain(after);
+        let config = Config::from_args(args.map(|arg| arg.to_string()));
+        std::fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn quoting() {
+        let config = parse(r#"data_dir = "/data # not a comment = still" "#, &[]).unwrap();
+        assert_eq!(
+            config.data_dir,
+            PathBuf::from("/data # not a comment = still")
+        );
+        let config = parse("data_dir=\"\"\nreplica_token = \"a\tb\"", &[]).unwrap();
+        assert_eq!(config.data_dir, PathBuf::new());
+        assert_eq!(config.replica_token.as_deref(), Some("a\tb"));
+
+        let err = parse("data_dir = \"/data", &[]).unwrap_err();
+        assert!(err.ends_with(":1: unterminated string"), "{err}");
+        let err = parse(r#"data_dir = "C:\data""#, &[]).unwrap_err();
+        assert!(
+            err.ends_with(":1: escapes in strings are not supported"),
+            "{err}"
+        );
+        let err = parse(r#"data_dir = "/a" "/b""#, &[]).unwrap_err();
+        assert!(err.ends_with(":1: unexpected text after value"), "{err}");
+        let err = parse("data_dir = /data", &[]).unwrap_err();
+        assert!(err.ends_with(":1: invalid value \"/data\""), "{err}");
+    }
+
+    #[test]
+    fn comments() {
+        let file = "# settings\n\n  # indented\ndata_dir = \"/data\" # trailing\n\
+            max_connections = 8# no space\nread_timeout = 5\t# tab\n";
+        let config = parse(file, &[]).unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/data"));
+        assert_eq!(config.max_connections, 8);
+        assert_eq!(config.timeouts.read, Duration::from_secs(5));
+        assert!(
+            parse("data_dir \"/data\"", &[])
+                .unwrap_err()
+                .ends_with(":1: expected `key = value`")
+        );
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let err = parse("data_dir = \"/data\"\ndata_dri = \"/data\"", &[]).unwrap_err();
+        assert!(err.ends_with(":2: unknown setting \"data_dri\""), "{err}");
+        let err = parse("data_dir = \"/data\"", &["--max-conections", "8"]).unwrap_err();
+        assert_eq!(err, "--max-conections: unknown setting \"max_conections\"");
+    }
+
+    #[test]
+    fn wrong_types() {
+        let err = parse("data_dir = 1", &[]).unwrap_err();
+        assert!(err.ends_with(":1: data_dir must be a string"), "{err}");
+        let err = parse("data_dir = \"/data\"\nread_timeout = \"5\"", &[]).unwrap_err();
+        assert!(
+            err.ends_with(":2: read_timeout must be a non-negative integer"),
+            "{err}"
+        );
+        let err = parse("data_dir = \"/data\"", &["--read-timeout", "-5"]).unwrap_err();
+        assert_eq!(
+            err,
+            "--read-timeout: read_timeout must be a non-negative integer"
+        );
+        let err = parse("data_dir = \"/data\"\nmax_connections = 0", &[]).unwrap_err();
+        assert!(
+            err.ends_with(":2: max_connections must be positive"),
+            "{err}"
+        );
+        let err = parse("data_dir = \"/data\"", &["--max-frame-len", "2147483648"]).unwrap_err();
+        assert_eq!(
+            err,
+            "--max-frame-len: max_frame_len must be positive and under 2 GiB"
+        );
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let file = "data_dir = \"/a\"\nnamespace.x = \"/x1\"\nnamespace.y = \"/y\"\n\
+            data_dir = \"/b\"\nnamespace.x = \"/x2\"";
+        let config = parse(file, &[]).unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/b"));
+        let namespaces = [
+            ("y".to_string(), "/y".into()),
+            ("x".to_string(), "/x2".into()),
+        ];
+        assert_eq!(config.namespaces, namespaces);
+    }
+
+    #[test]
+    fn flags_override_file() {
+        let file = "data_dir = \"/file\"\nread_timeout = 10\nmin_durability = \"always\"";
+        let flags = [
+            "--read-timeout",
+            "20",
+            "--data-dir",
+            "/flag",
+            "--namespace.Research",
+            "/r",
+        ];
+        let config = parse(file, &flags).unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/flag"));
+        assert_eq!(config.timeouts.read, Duration::from_secs(20));
+        assert_eq!(config.min_durability, Durability::Always);
+        assert_eq!(config.namespaces, [("Research".to_string(), "/r".into())]);
+
+        // Flags before `--config` still win.
+        let flags = ["--write-timeout", "7", "--data-dir", "/flag"];
+        let config = parse_around(&flags, file, &[]).unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/flag"));
+        assert_eq!(config.timeouts.write, Duration::from_secs(7));
+    }
+
+    #[test]
+    fn bind_addresses() {
+        let file = "data_dir = \"/data\"\nbind = \"127.0.0.1:1,[::1]:2 , unix:/run/z.sock\"";
+        let config = parse(file, &[]).unwrap();
+        let bind = [
+            BindAddr::Tcp(([127, 0, 0, 1], 1).into()),
+            BindAddr::Tcp("[::1]:2".parse().unwrap()),
+            BindAddr::Unix("/run/z.sock".into()),
+        ];
+        assert_eq!(config.bind, bind);
+
+        let config = parse("data_dir = \"/data\"", &["--bind", "0.0.0.0:3"]).unwrap();
+        assert_eq!(config.bind, [BindAddr::Tcp(([0, 0, 0, 0], 3).into())]);
+        let err = parse("data_dir = \"/data\"", &["--bind", "0.0.0.0:3,"]).unwrap_err();
+        assert_eq!(err, "--bind: invalid address \"\"");
+        let err = parse("data_dir = \"/data\"\nbind = \"unix:\"", &[]).unwrap_err();
+        assert!(err.ends_with(":2: invalid address \"unix:\""), "{err}");
+    }
+
+    #[test]
+    fn defaults() {
+        let config = parse("data_dir = \"/data\"", &[]).unwrap();
+        assert_eq!(config.bind, [BindAddr::Tcp(([127, 0, 0, 1], 9867).into())]);
+        assert_eq!(config.max_connections, 1024);
+        assert_eq!(config.limits.max_frame_len, 256 << 20);
+        assert_eq!(config.limits.max_request_len, 1 << 30);
+        assert_eq!(config.min_durability, Durability::Never);
+        assert_eq!(config.write_coalesce, None);
+        assert_eq!(parse("", &[]).unwrap_err(), "data_dir is required");
+    }
+}
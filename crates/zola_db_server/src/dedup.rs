@@ -0,0 +1,76 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OnceCell;
+use zola_db::EpochDay;
+use zola_db_proto::{ErrorCode, MAX_WRITE_TOKEN_LEN};
+
+use crate::Failure;
+
+/// How many write tokens a namespace remembers.
+const CAPACITY: usize = 1 << 16;
+
+/// The tokens of the writes most recently applied to a namespace, so that a
+/// write retried after a timeout or reconnect is applied only once. Tokens
+/// are scoped to the table and day written, so that clients writing
+/// different tables can't collide, and are kept in memory, so a restart
+/// forgets them.
+#[derive(Default)]
+pub struct Dedup {
+    state: Mutex<State>,
+}
+
+/// A write's table, day and token.
+type Key = (String, EpochDay, String);
+
+#[derive(Default)]
+struct State {
+    /// Set once the write with the key succeeds.
+    applied: HashMap<Key, Arc<OnceCell<()>>>,
+    /// The keys in `applied`, oldest first.
+    order: VecDeque<Key>,
+}
+
+impl Dedup {
+    /// Runs `write`, to `table` for `day`, unless a write to them with
+    /// `token` already succeeded, waiting for one in progress to finish
+    /// first. Returns whether it ran; a failed write leaves the token free
+    /// for the retry.
+    pub async fn once(
+        &self,
+        table: &str,
+        day: EpochDay,
+        token: String,
+        write: impl Future<Output = Result<(), Failure>>,
+    ) -> Result<bool, Failure> {
+        if token.len() > MAX_WRITE_TOKEN_LEN {
+            let msg = format!("write token over {MAX_WRITE_TOKEN_LEN} bytes");
+            return Err((ErrorCode::InvalidInput, msg));
+        }
+        let key = (table.to_string(), day, token);
+        let cell = {
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+            match state.applied.get(&key) {
+                Some(cell) => Arc::clone(cell),
+                None => {
+                    let cell = Arc::new(OnceCell::new());
+                    state.applied.insert(key.clone(), Arc::clone(&cell));
+                    state.order.push_back(key);
+                    if state.order.len() > CAPACITY {
+                        let oldest = state.order.pop_front().unwrap();
+                        state.applied.remove(&oldest);
+                    }
+                    cell
+                }
+            }
+        };
+        let mut ran = false;
+        cell.get_or_try_init(|| {
+            ran = true;
+            write
+        })
+        .await?;
+        Ok(ran)
+    }
+}
@@ -1,98 +1,662 @@
+mod auth;
 mod binance;
+mod coalesce;
+mod config;
+mod dedup;
+mod listen;
+mod replica;
+mod tail;
 
-use std::sync::{Arc, RwLock};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
+use arrow::array::types::{Int16Type, Int32Type, Int64Type, RunEndIndexType};
+use arrow::array::{Array, ArrayRef, AsArray, PrimitiveArray, RunArray};
+use arrow::datatypes::{ArrowNativeType, DataType};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
 use reqwest::Client;
 use tokio::net::TcpListener;
-use zola_db::Db;
-use zola_db_proto::{Request, Response};
+use tokio::sync::{Semaphore, watch};
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use zola_db::{Db, Durability, WriteMode};
+use zola_db_proto::{Compression, DEFAULT_NAMESPACE, ErrorCode, GOAWAY_ID, Limits, PartitionInfo, Request, Response, TableInfo};
+
+use crate::auth::{Acl, Permission, Tokens};
+use crate::coalesce::Coalescer;
+use crate::config::{Config, Timeouts, USAGE};
+use crate::dedup::Dedup;
+use crate::listen::{Listener, Stream};
+
+/// The most bytes of a partition file sent in reply to one
+/// [`Request::ReadPartitionFile`].
+const MAX_FILE_CHUNK: u64 = 64 << 20;
+
+struct Server {
+    /// The data roots requests select by name, including [`DEFAULT_NAMESPACE`].
+    namespaces: HashMap<String, Namespace>,
+    http: Client,
+    /// `None` disables authentication: every connection may read and write.
+    tokens: Option<Tokens>,
+    limits: Limits,
+    timeouts: Timeouts,
+    /// Following a primary, so refusing writes.
+    replica: bool,
+    /// Overrides the durability of [`Request::Write`]s that ask for less.
+    min_durability: Durability,
+    /// Set on SIGTERM, SIGINT or [`Request::Drain`], closing connections
+    /// between requests.
+    shutdown: watch::Sender<bool>,
+}
+
+/// One independent data root.
+struct Namespace {
+    db: Arc<Db>,
+    dir: PathBuf,
+    /// Merges appends from [`Request::Write`], if enabled.
+    coalescer: Option<Arc<Coalescer>>,
+    /// Each committed write, for [`tail`].
+    tail: tail::Feed,
+    /// The tokens of recent writes, so retries aren't applied twice.
+    applied: Dedup,
+}
 
 #[tokio::main]
 async fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 || args.len() > 3 {
-        eprintln!("usage: {} <db-path> [bind-addr]", args[0]);
+    let config = Config::from_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
         std::process::exit(1);
+    });
+    let tokens = config.tokens.as_ref().map(|path| {
+        Tokens::load(path).unwrap_or_else(|e| {
+            eprintln!("failed to load tokens: {e}");
+            std::process::exit(1);
+        })
+    });
+    let mut namespaces = HashMap::new();
+    let dirs = std::iter::once((DEFAULT_NAMESPACE.to_string(), config.data_dir))
+        .chain(config.namespaces);
+    for (name, dir) in dirs {
+        let db = Db::open(&dir)
+            .unwrap_or_else(|e| panic!("failed to open database of namespace {name:?}: {e}"));
+        let db = Arc::new(db);
+        let coalescer = config
+            .write_coalesce
+            .map(|window| Coalescer::new(Arc::clone(&db), window));
+        let namespace = Namespace {
+            db,
+            dir,
+            coalescer,
+            tail: tail::Feed::default(),
+            applied: Dedup::default(),
+        };
+        namespaces.insert(name, namespace);
     }
-    let db_path = &args[1];
-    let bind = args.get(2).map_or("127.0.0.1:9867", |s| s.as_str());
+    let server = Arc::new(Server {
+        namespaces,
+        http: Client::new(),
+        tokens,
+        limits: config.limits,
+        timeouts: config.timeouts,
+        replica: config.replica_of.is_some(),
+        min_durability: config.min_durability,
+        shutdown: watch::Sender::new(false),
+    });
 
-    let db = Db::open(db_path).expect("failed to open database");
-    let db = Arc::new(RwLock::new(db));
-    let client = Client::new();
+    // Each namespace follows the one of the same name on the primary.
+    if let Some(addr) = config.replica_of {
+        for (name, namespace) in &server.namespaces {
+            let mut primary = zola_db_client::Client::builder()
+                .namespace(name)
+                .compression(Compression::Zstd);
+            if let Some(token) = &config.replica_token {
+                primary = primary.token(token);
+            }
+            let primary = primary.build(addr.clone());
+            // Hidden from `Db::open`, like other staging directories.
+            let staging = namespace.dir.join(".replica");
+            if namespace.db.tables().is_empty() {
+                eprintln!("copying the primary's partitions of namespace {name:?} before serving");
+                replica::bootstrap(&namespace.db, &primary, &staging).await;
+            }
+            tokio::spawn(replica::follow(Arc::clone(&namespace.db), primary, staging));
+        }
+    }
 
-    let listener = TcpListener::bind(bind).await.expect("failed to bind");
-    eprintln!("listening on {bind}");
+    let mut listeners = Vec::new();
+    for addr in &config.bind {
+        let listener = Listener::bind(addr)
+            .await
+            .unwrap_or_else(|e| panic!("failed to bind {addr}: {e}"));
+        eprintln!("listening on {addr}");
+        listeners.push(listener);
+    }
+    let mut tails = None;
+    if let Some(addr) = config.ws_bind {
+        let listener = TcpListener::bind(addr).await.expect("failed to bind");
+        eprintln!("serving live tails on {addr}");
+        tails = Some(tokio::spawn(tail::serve(listener, Arc::clone(&server))));
+    }
 
+    // Once `max_connections` are open, stop accepting until one closes; further
+    // clients wait in the listen backlog.
+    let limit = Arc::new(Semaphore::new(config.max_connections));
+    let mut connections = JoinSet::new();
+    let signal = shutdown_signal();
+    tokio::pin!(signal);
+    let mut drain = server.shutdown.subscribe();
     loop {
-        let (stream, _) = match listener.accept().await {
-            Ok(conn) => conn,
-            Err(e) => {
-                eprintln!("accept error: {e}");
-                continue;
-            }
+        let permit = tokio::select! {
+            _ = &mut signal => break,
+            _ = drain.wait_for(|&stop| stop) => break,
+            permit = Arc::clone(&limit).acquire_owned() => permit.unwrap(),
+        };
+        let stream = tokio::select! {
+            _ = &mut signal => break,
+            _ = drain.wait_for(|&stop| stop) => break,
+            conn = listen::accept(&listeners) => match conn {
+                Ok(conn) => conn,
+                Err(e) => {
+                    eprintln!("accept error: {e}");
+                    continue;
+                }
+            },
         };
-        let db = Arc::clone(&db);
-        let client = client.clone();
-        tokio::spawn(async move {
-            if let Err(e) = handle(stream, db, client).await {
+        let server = Arc::clone(&server);
+        connections.spawn(async move {
+            if let Err(e) = handle(stream, server).await {
                 eprintln!("connection error: {e}");
             }
+            drop(permit);
         });
+        while connections.try_join_next().is_some() {}
+    }
+
+    // Stop accepting, close idle connections, and give in-flight requests —
+    // ingests in particular — until the deadline to finish. Past it, exiting
+    // abandons them; a commit cut short is rolled back or forward on the next
+    // open.
+    drop(listeners);
+    server.shutdown.send_replace(true);
+    while connections.try_join_next().is_some() {}
+    eprintln!("shutting down; waiting for {} connections", connections.len());
+    let drained = timeout(server.timeouts.shutdown, async {
+        while connections.join_next().await.is_some() {}
+        if let Some(tails) = tails {
+            let _ = tails.await;
+        }
+    });
+    if drained.await.is_err() {
+        eprintln!("shutdown deadline passed; abandoning {} connections", connections.len());
+        std::process::exit(1);
     }
 }
 
-/// Handles a single request-response exchange on `stream`.
-///
-/// A panic inside `spawn_blocking` poisons the `RwLock`, which is intentional:
-/// subsequent requests will fail rather than operate on potentially corrupt state.
+/// Completes on SIGINT or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut term = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.expect("failed to install SIGINT handler");
+}
+
+/// Serves requests on `stream` until the client closes it. Each connection is
+/// a task, so idle clients cost no threads; queries and ingests run on the
+/// blocking pool.
 async fn handle(
-    mut stream: tokio::net::TcpStream,
-    db: Arc<RwLock<Db>>,
-    client: Client,
+    mut stream: Stream,
+    server: Arc<Server>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    stream.set_nodelay(true)?;
+    let timeouts = &server.timeouts;
+    let mut shutdown = server.shutdown.subscribe();
+    let mut acl = None;
+    let mut compression = Compression::None;
+    loop {
+        tokio::select! {
+            // Checked first, so that a request sent after shutdown began is
+            // left unread, as the client is told. Dropping the guard
+            // `wait_for` returns, which mustn't be held across the write.
+            biased;
+            _ = async { drop(shutdown.wait_for(|&stop| stop).await) } => {
+                let write = zola_db_proto::write_response(&mut stream, GOAWAY_ID, &Response::GoAway, compression);
+                timeout(timeouts.write, write).await??;
+                return Ok(());
+            }
+            ready = timeout(timeouts.idle, stream.readable()) => {
+                if ready.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        let head = zola_db_proto::read_request(&mut stream, compression, &server.limits, timeouts.read).await?;
+        let Some(head) = head else {
+            return Ok(());
+        };
+        // Turned away before its body is read, so that a client without a
+        // token can't make the server hold one.
+        if server.tokens.is_some() && acl.is_none() && head.needs_auth() {
+            let write = zola_db_proto::write_response(&mut stream, head.id, &Response::Unauthenticated, compression);
+            timeout(timeouts.write, write).await??;
+            if head.has_body() {
+                return Ok(());
+            }
+            continue;
+        }
+        let id = head.id;
+        let (id, namespace, request) = match head.read_body(&mut stream).await {
+            Ok(request) => request,
+            // The rest of the body is left unread, so the connection ends.
+            Err(e) => {
+                let response = Response::Error(ErrorCode::InvalidInput, e.to_string());
+                let write = zola_db_proto::write_response(&mut stream, id, &response, compression);
+                timeout(timeouts.write, write).await??;
+                return Err(e.into());
+            }
+        };
+        let namespace = namespace.as_deref().unwrap_or(DEFAULT_NAMESPACE);
+        let (response, subscription) = match request {
+            Request::Negotiate {
+                compression: offered,
+            } => {
+                // Every method is available, so the client's first choice wins.
+                let chosen = offered.into_iter().next().unwrap_or_default();
+                (Response::Negotiate(chosen), None)
+            }
+            Request::Ping => (Response::Ping(env!("CARGO_PKG_VERSION").to_string()), None),
+            Request::Subscribe { table, symbols } => {
+                match tail::subscribe(&server, acl, namespace, &table, symbols) {
+                    Ok(subscription) => (Response::Subscribe, Some(subscription)),
+                    Err(response) => (response, None),
+                }
+            }
+            request => (respond(namespace, request, &server, &mut acl).await, None),
+        };
+        if let Response::Error(_, msg) = &response {
+            eprintln!("request {id} failed: {msg}");
+        }
+        let write = zola_db_proto::write_response(&mut stream, id, &response, compression);
+        timeout(timeouts.write, write).await??;
+        if let Response::Negotiate(chosen) = response {
+            compression = chosen;
+        }
+        if let Some(subscription) = subscription {
+            return tail::push(&mut stream, id, subscription, &server, compression).await;
+        }
+    }
+}
 
-    let request = zola_db_proto::read_request(&mut stream).await?;
+/// Executes one request against `namespace`, if the connection's [`Acl`]
+/// permits it; failures become [`Response::Error`] so the connection stays
+/// usable.
+///
+/// A panic inside an ingest poisons the `Db`'s writer lock for that table,
+/// which is intentional: subsequent ingests to it will fail rather than build
+/// on potentially corrupt state. Queries read consistent table snapshots and
+/// are unaffected.
+async fn respond<'a>(
+    namespace: &str,
+    request: Request,
+    server: &'a Server,
+    acl: &mut Option<&'a Acl>,
+) -> Response {
+    if let Request::Auth { token } = &request {
+        let Some(tokens) = &server.tokens else {
+            return Response::Auth;
+        };
+        *acl = tokens.get(token);
+        return match acl {
+            Some(_) => Response::Auth,
+            None => Response::Unauthenticated,
+        };
+    }
+    let Some(ns) = server.namespaces.get(namespace) else {
+        let msg = format!("unknown namespace {namespace:?}");
+        return Response::Error(ErrorCode::NotFound, msg);
+    };
+    // Checked before the ACL, whose prefix grants would otherwise match
+    // names that escape the table directory, such as `md_/../x`.
+    let table = match &request {
+        Request::JoinAsof { table, .. }
+        | Request::GetSchema { table }
+        | Request::Range { table, .. }
+        | Request::GetPartition { table, .. }
+        | Request::ReadPartitionFile { table, .. }
+        | Request::DropTable { table }
+        | Request::Write { table, .. }
+        | Request::DropPartitionsBefore { table, .. } => Some(table),
+        _ => None,
+    };
+    if let Some(Err(e)) = table.map(|table| Db::check_table_name(table)) {
+        let (code, msg) = failure(e);
+        return Response::Error(code, msg);
+    }
+    let acl = *acl;
+    let open = server.tokens.is_none();
+    let permits = |table: &str, permission| {
+        open || acl.is_some_and(|acl| acl.permits(namespace, table, permission))
+    };
+    let allowed = match &request {
+        Request::JoinAsof { table, .. }
+        | Request::GetSchema { table }
+        | Request::Range { table, .. }
+        | Request::GetPartition { table, .. }
+        | Request::ReadPartitionFile { table, .. } => permits(table, Permission::Read),
+        Request::IngestBinance { market, .. } => {
+            permits(binance::table_name(*market), Permission::Write)
+        }
+        Request::DropTable { table } | Request::Write { table, .. } => {
+            permits(table, Permission::Write)
+        }
+        Request::DropPartitionsBefore { table, .. } => permits(table, Permission::Admin),
+        // Filtered to what the connection may read.
+        Request::ListTables | Request::ListPartitions => open || acl.is_some(),
+        Request::Refresh => {
+            open || acl.is_some_and(|acl| acl.permits_all(namespace, Permission::Admin))
+        }
+        Request::Drain => {
+            open || acl.is_some_and(|acl| {
+                (server.namespaces.keys()).all(|ns| acl.permits_all(ns, Permission::Admin))
+            })
+        }
+        Request::Auth { .. }
+        | Request::Subscribe { .. }
+        | Request::Negotiate { .. }
+        | Request::Ping => unreachable!(),
+    };
+    if !allowed {
+        return Response::Unauthenticated;
+    }
+    let writes = matches!(
+        request,
+        Request::IngestBinance { .. }
+            | Request::DropTable { .. }
+            | Request::DropPartitionsBefore { .. }
+            | Request::Write { .. }
+    );
+    if server.replica && writes {
+        let msg = "this server is a read-only replica".to_string();
+        return Response::Error(ErrorCode::ReadOnly, msg);
+    }
 
-    match request {
+    let db = Arc::clone(&ns.db);
+    let client = &server.http;
+    let result = match request {
         Request::JoinAsof {
             table,
             symbol,
             direction,
             timestamps,
-        } => {
-            let batch = tokio::task::spawn_blocking(move || {
-                let db = db.read().unwrap();
-                db.join_asof(&table, &symbol, &timestamps, direction)
-            })
-            .await??;
-
-            zola_db_proto::write_response(&mut stream, &Response::JoinAsof(batch)).await?;
-        }
+        } => tokio::task::spawn_blocking(move || {
+            db.join_asof(&table, &symbol, &timestamps, direction)
+                .map(Response::JoinAsof)
+                .map_err(failure)
+        })
+        .await,
         Request::IngestBinance { market, day } => {
-            let symbols = binance::list_symbols(&client, market).await?;
-            let fetch_result = binance::fetch(&client, market, &symbols, day).await;
-            let response = tokio::task::spawn_blocking(move || {
+            let fetch_result = match binance::list_symbols(client, market).await {
+                Ok(symbols) => binance::fetch(client, market, &symbols, day).await,
+                Err(e) => Err(e),
+            };
+            let tail = ns.tail.clone();
+            tokio::task::spawn_blocking(move || {
                 let epoch_day = zola_db::EpochDay::from(day);
-                match fetch_result {
-                    Ok(Some(batch)) => {
+                match fetch_result.map_err(|e| (ErrorCode::Unavailable, e.to_string()))? {
+                    Some(batch) => {
                         let table = binance::table_name(market);
-                        let mut db = db.write().unwrap();
-                        match db.ingest(table, epoch_day, batch) {
-                            Ok(()) => Response::IngestBinance,
-                            Err(e) => Response::Error(e.to_string()),
-                        }
+                        db.ingest(table, epoch_day, batch.clone(), WriteMode::Overwrite)
+                            .map_err(failure)?;
+                        tail.publish(table.to_string(), batch);
+                        Ok(Response::IngestBinance)
                     }
-                    Ok(None) => Response::IngestBinance,
-                    Err(e) => Response::Error(e.to_string()),
+                    None => Ok(Response::IngestBinance),
                 }
             })
-            .await?;
+            .await
+        }
+        Request::ListTables => {
+            let tables = db
+                .tables()
+                .into_iter()
+                .filter(|table| permits(&table.name, Permission::Read))
+                .map(|table| TableInfo {
+                    keys: zola_db::key_columns(&table.schema),
+                    name: table.name,
+                    schema: table.schema,
+                    days: table
+                        .days
+                        .map(|days| ((*days.start()).into(), (*days.end()).into())),
+                })
+                .collect();
+            Ok(Ok(Response::ListTables(tables)))
+        }
+        Request::GetSchema { table } => Ok(db
+            .schema(&table)
+            .map(Response::GetSchema)
+            .map_err(failure)),
+        Request::Range {
+            table,
+            symbol,
+            start,
+            end,
+            columns,
+        } => tokio::task::spawn_blocking(move || {
+            let columns: Option<Vec<&str>> =
+                columns.as_ref().map(|c| c.iter().map(String::as_str).collect());
+            db.range(&table, &symbol, start..end, columns.as_deref())
+                .map(Response::Range)
+                .map_err(failure)
+        })
+        .await,
+        Request::DropTable { table } => tokio::task::spawn_blocking(move || {
+            db.drop_table(&table)
+                .map(|()| Response::DropTable)
+                .map_err(failure)
+        })
+        .await,
+        Request::Refresh => tokio::task::spawn_blocking(move || {
+            let added = db.refresh().map_err(failure)?;
+            let added = added
+                .into_iter()
+                .map(|(table, day)| (table, day.into()))
+                .collect();
+            Ok(Response::Refresh(added))
+        })
+        .await,
+        Request::DropPartitionsBefore { table, day } => tokio::task::spawn_blocking(move || {
+            let dropped = db
+                .drop_partitions_before(&table, day.into())
+                .map_err(failure)?;
+            let dropped = dropped.into_iter().map(Into::into).collect();
+            Ok(Response::DropPartitionsBefore(dropped))
+        })
+        .await,
+        Request::Write {
+            table,
+            day,
+            mode,
+            durability,
+            token,
+            batches,
+        } => {
+            let batch = match tokio::task::spawn_blocking(|| concat(batches)).await {
+                Ok(Ok(batch)) => batch,
+                Ok(Err((code, msg))) => return Response::Error(code, msg),
+                Err(e) => return Response::Error(ErrorCode::Internal, e.to_string()),
+            };
+            let durability = match server.min_durability {
+                Durability::Always => Durability::Always,
+                Durability::Never => durability,
+            };
+            let written = (table.clone(), batch.clone());
+            let write = async {
+                match (&ns.coalescer, mode) {
+                    (Some(coalescer), WriteMode::Append) => {
+                        coalescer.append(table, day.into(), batch, durability).await
+                    }
+                    _ => tokio::task::spawn_blocking(move || {
+                        db.ingest_days_with(&table, vec![(day.into(), batch)], mode, durability)
+                            .map(drop)
+                            .map_err(failure)
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err((ErrorCode::Internal, e.to_string()))),
+                }
+            };
+            let applied = match token {
+                Some(token) => ns.applied.once(&written.0, day.into(), token, write).await,
+                None => write.await.map(|()| true),
+            };
+            if let Ok(true) = applied {
+                let (table, batch) = written;
+                ns.tail.publish(table, batch);
+            }
+            Ok(applied.map(|_| Response::Write))
+        }
+        // Checksums not yet computed take a pass over their partitions.
+        Request::ListPartitions => tokio::task::spawn_blocking(move || db.partitions())
+            .await
+            .map(|partitions| {
+                let partitions = partitions
+                    .map_err(failure)?
+                    .into_iter()
+                    .filter(|p| permits(&p.table, Permission::Read))
+                    .map(|p| PartitionInfo {
+                        table: p.table,
+                        day: p.day.into(),
+                        rows: p.rows as u64,
+                        bytes: p.bytes,
+                        checksum: p.checksum,
+                    })
+                    .collect();
+                Ok(Response::ListPartitions(partitions))
+            }),
+        Request::GetPartition { table, day } => Ok(db
+            .partition(&table, day.into())
+            .map(Response::GetPartition)
+            .map_err(failure)),
+        Request::ReadPartitionFile {
+            table,
+            day,
+            checksum,
+            offset,
+            len,
+        } => tokio::task::spawn_blocking(move || {
+            let range = offset..offset.saturating_add(len.min(MAX_FILE_CHUNK));
+            db.read_partition_file(&table, day.into(), checksum, range)
+                .map(Response::ReadPartitionFile)
+                .map_err(failure)
+        })
+        .await,
+        Request::Drain => {
+            eprintln!("draining at a client's request");
+            server.shutdown.send_replace(true);
+            Ok(Ok(Response::Drain))
+        }
+        Request::Auth { .. }
+        | Request::Subscribe { .. }
+        | Request::Negotiate { .. }
+        | Request::Ping => unreachable!(),
+    };
+    match result {
+        Ok(Ok(response)) => response,
+        Ok(Err((code, msg))) => Response::Error(code, msg),
+        Err(e) => Response::Error(ErrorCode::Internal, e.to_string()),
+    }
+}
+
+/// The rows of the `batches` of a [`Request::Write`] as one batch, with the
+/// runs of run-end encoded columns that carry on from one batch into the
+/// next joined, as the `Db` requires of a key's rows.
+fn concat(mut batches: Vec<RecordBatch>) -> Result<RecordBatch, Failure> {
+    let invalid = |e: ArrowError| (ErrorCode::InvalidInput, e.to_string());
+    if batches.len() <= 1 {
+        let msg = "a write needs at least one batch";
+        return batches.pop().ok_or((ErrorCode::InvalidInput, msg.to_string()));
+    }
+    let schema = batches[0].schema();
+    if let Some(batch) = batches.iter().find(|batch| batch.schema().fields() != schema.fields()) {
+        let msg = format!("expected schema {:?}, got {:?}", schema.fields(), batch.schema().fields());
+        return Err(invalid(ArrowError::SchemaError(msg)));
+    }
+    let columns = (0..schema.fields().len())
+        .map(|i| {
+            let columns: Vec<&dyn Array> = batches.iter().map(|batch| batch.column(i).as_ref()).collect();
+            match schema.field(i).data_type() {
+                DataType::RunEndEncoded(run_ends, _) => match run_ends.data_type() {
+                    DataType::Int16 => join_runs::<Int16Type>(&columns),
+                    DataType::Int32 => join_runs::<Int32Type>(&columns),
+                    _ => join_runs::<Int64Type>(&columns),
+                },
+                _ => arrow::compute::concat(&columns),
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map_err(invalid)?;
+    RecordBatch::try_new(schema, columns).map_err(invalid)
+}
 
-            zola_db_proto::write_response(&mut stream, &response).await?;
+/// Concatenates run-end encoded `columns`, extending the last run of each
+/// into the first of the next where their values are equal.
+fn join_runs<R: RunEndIndexType>(columns: &[&dyn Array]) -> Result<ArrayRef, ArrowError> {
+    let too_long = || ArrowError::InvalidArgumentError("too many rows for the run ends' type".into());
+    let mut run_ends: Vec<R::Native> = Vec::new();
+    let mut values: Vec<ArrayRef> = Vec::new();
+    // The rows so far and the value of their last run.
+    let mut len = 0;
+    let mut last: Option<ArrayRef> = None;
+    for column in columns {
+        let runs = column.as_run::<R>();
+        if runs.is_empty() {
+            continue;
+        }
+        let runs_values = runs.values_slice();
+        let mut ends = runs.run_ends().sliced_values().map(|end| end.as_usize() + len);
+        let first = runs_values.slice(0, 1);
+        if last.is_some_and(|last| last.to_data() == first.to_data()) {
+            *run_ends.last_mut().unwrap() = R::Native::from_usize(ends.next().unwrap()).ok_or_else(too_long)?;
+            values.push(runs_values.slice(1, runs_values.len() - 1));
+        } else {
+            values.push(Arc::clone(&runs_values));
+        }
+        for end in ends {
+            run_ends.push(R::Native::from_usize(end).ok_or_else(too_long)?);
         }
+        len += runs.len();
+        last = Some(runs_values.slice(runs_values.len() - 1, 1));
     }
+    let values: Vec<&dyn Array> = values.iter().map(AsRef::as_ref).collect();
+    let run_ends = PrimitiveArray::<R>::from_iter_values(run_ends);
+    Ok(Arc::new(RunArray::<R>::try_new(&run_ends, arrow::compute::concat(&values)?.as_ref())?))
+}
+
+/// Why a request failed, as [`Response::Error`] reports it.
+type Failure = (ErrorCode, String);
 
-    Ok(())
+fn failure(e: zola_db::Error) -> Failure {
+    use zola_db::Error;
+    let code = match &e {
+        Error::TableNotFound(_) => ErrorCode::NotFound,
+        Error::TableExists(_) | Error::PartitionExists(..) => ErrorCode::AlreadyExists,
+        Error::NonContiguousSymbol(_)
+        | Error::UnsortedTimestamps(_)
+        | Error::NullValues(_)
+        | Error::InvalidInterval(_)
+        | Error::InvalidTableName(_)
+        | Error::Arrow(_) => ErrorCode::InvalidInput,
+        Error::QuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+        Error::ReadOnly => ErrorCode::ReadOnly,
+        Error::PartitionChanged(..) => ErrorCode::Conflict,
+        Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound => ErrorCode::NotFound,
+        Error::Io(_) | Error::InvalidFile(_) => ErrorCode::Internal,
+    };
+    (code, e.to_string())
 }
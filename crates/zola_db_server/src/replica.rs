@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow::ipc::reader::FileReader;
+use tokio::io::AsyncWriteExt;
+use zola_db::{Db, EpochDay};
+use zola_db_client::{Client, PartitionInfo};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long to wait between polls of the primary.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much of a partition file to ask the primary for at once.
+const CHUNK: u64 = 8 << 20;
+
+/// Keeps `db` a copy of the tables of the primary that `primary` may read.
+/// Each poll lists the primary's partitions and copies the files of those
+/// whose checksum differs from the copy's; tables and days the primary no
+/// longer has are dropped. Runs until the process exits, logging errors and
+/// retrying at the next poll.
+///
+/// Files download into `staging`, a chunk at a time, so a transfer cut off
+/// by an error or restart resumes where it stopped. Each is checked against
+/// its checksum before it replaces the partition.
+pub async fn follow(db: Arc<Db>, primary: Client, staging: PathBuf) {
+    let mut applied = None;
+    loop {
+        if let Err(e) = sync(&db, &primary, &staging, &mut applied).await {
+            eprintln!("replication error: {e}");
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// The primary's checksum of each partition copied, by table and day.
+type Applied = HashMap<(String, EpochDay), u32>;
+
+async fn sync(
+    db: &Arc<Db>,
+    primary: &Client,
+    staging: &Path,
+    applied: &mut Option<Applied>,
+) -> Result<(), Error> {
+    // Listed from `db` on the first poll, so that partitions copied before a
+    // restart aren't copied again. Checksums not yet known take a pass over
+    // their files.
+    let applied = match applied {
+        Some(applied) => applied,
+        None => {
+            let db = Arc::clone(db);
+            let partitions = tokio::task::spawn_blocking(move || db.partitions()).await??;
+            let partitions = partitions
+                .into_iter()
+                .map(|p| ((p.table, p.day), p.checksum));
+            applied.insert(partitions.collect())
+        }
+    };
+    let partitions = primary.list_partitions().await?;
+
+    let listed: HashSet<(&str, EpochDay)> = (partitions.iter())
+        .map(|p| (p.table.as_str(), p.day.into()))
+        .collect();
+    let tables: HashSet<&str> = listed.iter().map(|&(table, _)| table).collect();
+    for table in db.tables() {
+        if !tables.contains(table.name.as_str()) {
+            drop_table(db, table.name, applied).await?;
+        }
+    }
+    let mut gone: HashMap<String, Vec<EpochDay>> = HashMap::new();
+    for (table, day) in applied.keys() {
+        if !listed.contains(&(table.as_str(), *day)) {
+            gone.entry(table.clone()).or_default().push(*day);
+        }
+    }
+    for (table, days) in gone {
+        applied.retain(|(t, day), _| *t != table || !days.contains(day));
+        let db = Arc::clone(db);
+        tokio::task::spawn_blocking(move || db.drop_partitions(&table, &days)).await??;
+    }
+
+    for p in partitions {
+        let day = EpochDay::from(p.day);
+        if applied.get(&(p.table.clone(), day)) == Some(&p.checksum) {
+            continue;
+        }
+        let file = download(primary, staging, &p).await?;
+        let recreated = install(db, staging, &p.table, vec![(p.day, file)]).await?;
+        if recreated {
+            applied.retain(|(t, _), _| *t != p.table);
+        }
+        applied.insert((p.table, day), p.checksum);
+    }
+    Ok(())
+}
+
+async fn drop_table(db: &Arc<Db>, table: String, applied: &mut Applied) -> Result<(), Error> {
+    applied.retain(|(t, _), _| *t != table);
+    let db = Arc::clone(db);
+    tokio::task::spawn_blocking(move || db.drop_table(&table)).await??;
+    Ok(())
+}
+
+/// Copies every partition of the primary into `db`, which must be empty,
+/// before the replica serves queries, so that it never answers from a
+/// partial copy; [`follow`] then keeps it up to date. Files download into
+/// `staging` as for `follow`, and those of a table are added to `db` all at
+/// once. Retries until it succeeds.
+pub async fn bootstrap(db: &Arc<Db>, primary: &Client, staging: &Path) {
+    loop {
+        match try_bootstrap(db, primary, staging).await {
+            Ok(n) => {
+                eprintln!("copied {n} partitions from the primary");
+                return;
+            }
+            Err(e) => eprintln!("replication error: {e}"),
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn try_bootstrap(db: &Arc<Db>, primary: &Client, staging: &Path) -> Result<usize, Error> {
+    let partitions = primary.list_partitions().await?;
+    // Downloaded first, so a partition the primary changes meanwhile fails
+    // the attempt before any table is added; the next keeps the files
+    // already downloaded for partitions that didn't change.
+    let mut tables: Vec<(String, Vec<(jiff::civil::Date, PathBuf)>)> = Vec::new();
+    for p in &partitions {
+        let file = download(primary, staging, p).await?;
+        match tables.last_mut() {
+            Some((table, files)) if *table == p.table => files.push((p.day, file)),
+            _ => tables.push((p.table.clone(), vec![(p.day, file)])),
+        }
+    }
+    for (table, files) in tables {
+        install(db, staging, &table, files).await?;
+    }
+    Ok(partitions.len())
+}
+
+/// Downloads the file of `p` into `staging`, resuming an earlier download of
+/// the same version, and checks it. Returns the file's path.
+async fn download(primary: &Client, staging: &Path, p: &PartitionInfo) -> Result<PathBuf, Error> {
+    let dir = staging.join(&p.table);
+    tokio::fs::create_dir_all(&dir).await?;
+    let name = format!("{}.{:08x}", p.day, p.checksum);
+    let (part, done) = (
+        dir.join(format!("{name}.part")),
+        dir.join(format!("{name}.arrow")),
+    );
+    // Downloads of other versions of the partition are of no more use.
+    let prefix = format!("{}.", p.day);
+    let mut entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with(&prefix) && path != part && path != done
+        {
+            tokio::fs::remove_file(path).await?;
+        }
+    }
+    if tokio::fs::try_exists(&done).await? {
+        return Ok(done);
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&part)
+        .await?;
+    let mut offset = file.metadata().await?.len();
+    while offset < p.bytes {
+        let chunk = primary
+            .read_partition_file(&p.table, p.day, p.checksum, offset, CHUNK)
+            .await?;
+        if chunk.is_empty() {
+            break;
+        }
+        file.write_all(&chunk).await?;
+        offset += chunk.len() as u64;
+    }
+    file.flush().await?;
+    drop(file);
+
+    let check = part.clone();
+    let checksum = tokio::task::spawn_blocking(move || file_checksum(&check)).await??;
+    if offset != p.bytes || checksum != p.checksum {
+        tokio::fs::remove_file(&part).await?;
+        return Err(format!(
+            "copy of partition of table {:?} for {} is corrupt; retrying",
+            p.table, p.day,
+        )
+        .into());
+    }
+
+    tokio::fs::rename(&part, &done).await?;
+    Ok(done)
+}
+
+/// Adds the downloaded `files` of `table`, by day, to `db` in one write,
+/// replacing the table first if the primary recreated it with another
+/// schema. Returns whether it did.
+async fn install(
+    db: &Arc<Db>,
+    staging: &Path,
+    table: &str,
+    files: Vec<(jiff::civil::Date, PathBuf)>,
+) -> Result<bool, Error> {
+    let ready = staging.join(table).join("ready");
+    match tokio::fs::remove_dir_all(&ready).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    tokio::fs::create_dir(&ready).await?;
+    let mut first = None;
+    for (day, file) in files {
+        let path = ready.join(format!("{day}.arrow"));
+        tokio::fs::rename(file, &path).await?;
+        first.get_or_insert(path);
+    }
+    let Some(first) = first else {
+        return Ok(false);
+    };
+
+    let db = Arc::clone(db);
+    let table = table.to_string();
+    tokio::task::spawn_blocking(move || {
+        let schema = FileReader::try_new(File::open(first)?, None)?.schema();
+        // A different schema means the table was dropped and recreated.
+        let recreated = db
+            .schema(&table)
+            .is_ok_and(|current| current.fields() != schema.fields());
+        if recreated {
+            db.drop_table(&table)?;
+        }
+        db.ingest_external(&table, &ready)?;
+        Ok(recreated)
+    })
+    .await?
+}
+
+fn file_checksum(path: &Path) -> std::io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut crc = crc32fast::Hasher::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(crc.finalize()),
+            n => crc.update(&buf[..n]),
+        }
+    }
+}
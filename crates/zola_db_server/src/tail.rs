@@ -0,0 +1,352 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use arrow::array::{AsArray, BooleanArray};
+use arrow::compute::{cast, filter_record_batch};
+use arrow::datatypes::DataType;
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use zola_db::SYMBOL_COL;
+use zola_db_proto::{Compression, DEFAULT_NAMESPACE, ErrorCode, GOAWAY_ID};
+
+use crate::Server;
+use crate::auth::{Acl, Permission};
+use crate::listen::Stream;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How many writes a subscriber may fall behind before it is disconnected.
+const CAPACITY: usize = 1024;
+
+/// How many bytes of writes a subscriber may fall behind before it is
+/// disconnected, since until it catches up they are kept for it.
+const MAX_LAG_BYTES: u64 = 256 << 20;
+
+/// The writes committed to a namespace, for subscribers to follow.
+#[derive(Clone, Default)]
+pub struct Feed {
+    queues: Arc<Mutex<Vec<Queue>>>,
+}
+
+/// The writes to `table` waiting for one subscriber to send them.
+struct Queue {
+    table: String,
+    sender: mpsc::Sender<RecordBatch>,
+    /// The bytes of the writes queued, shared with the subscriber.
+    bytes: Arc<AtomicU64>,
+}
+
+impl Feed {
+    /// Sends the rows of `batch`, just written to `table`, to its
+    /// subscribers. A subscriber whose queue is full, in writes or bytes, is
+    /// disconnected rather than let fall further behind.
+    pub fn publish(&self, table: String, batch: RecordBatch) {
+        let bytes = batch.get_array_memory_size() as u64;
+        self.queues.lock().unwrap().retain(|queue| {
+            if queue.table != table {
+                return !queue.sender.is_closed();
+            }
+            queue.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes <= MAX_LAG_BYTES
+                && queue.sender.try_send(batch.clone()).is_ok()
+        });
+    }
+
+    /// Queues the writes to `table` for a new subscriber.
+    fn subscribe(&self, table: &str) -> (mpsc::Receiver<RecordBatch>, Arc<AtomicU64>) {
+        let (sender, receiver) = mpsc::channel(CAPACITY);
+        let bytes = Arc::<AtomicU64>::default();
+        self.queues.lock().unwrap().push(Queue {
+            table: table.to_string(),
+            sender,
+            bytes: Arc::clone(&bytes),
+        });
+        (receiver, bytes)
+    }
+}
+
+/// Serves live tails over WebSocket on `listener`, so that a browser can
+/// follow the rows written to a table as they are committed.
+///
+/// A client connects to `/<table>`, with optional query parameters
+/// `symbols` (comma-separated; rows of other symbols are left out),
+/// `namespace` and `token`. The token is a query parameter because browsers
+/// can't set headers on WebSocket requests. Each write then arrives as a text
+/// message holding a JSON array of its rows, one object per row.
+///
+/// Only writes made through this server are tailed; a replica's copies of the
+/// primary's are not. A client that falls more than [`CAPACITY`] writes or
+/// [`MAX_LAG_BYTES`] behind is disconnected with close code 1013 (try again
+/// later).
+///
+/// Returns once the server is shutting down and every client has been sent
+/// a close.
+pub async fn serve(listener: TcpListener, server: Arc<Server>) {
+    let mut shutdown = server.shutdown.subscribe();
+    let mut connections = JoinSet::new();
+    loop {
+        let stream = tokio::select! {
+            _ = shutdown.wait_for(|&stop| stop) => break,
+            conn = listener.accept() => match conn {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("accept error: {e}");
+                    continue;
+                }
+            },
+        };
+        let server = Arc::clone(&server);
+        connections.spawn(async move {
+            if let Err(e) = tail(stream, &server).await {
+                eprintln!("tail error: {e}");
+            }
+        });
+        while connections.try_join_next().is_some() {}
+    }
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+}
+
+pub struct Subscription {
+    /// `None` for all symbols.
+    symbols: Option<HashSet<String>>,
+    /// Closed once the subscriber falls too far behind.
+    receiver: mpsc::Receiver<RecordBatch>,
+    /// See [`Queue::bytes`].
+    bytes: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// The next write to the table, or `None` if the subscriber fell too far
+    /// behind.
+    async fn next(&mut self) -> Option<RecordBatch> {
+        let batch = self.receiver.recv().await?;
+        let bytes = batch.get_array_memory_size() as u64;
+        self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+        Some(batch)
+    }
+}
+
+async fn tail(stream: TcpStream, server: &Server) -> Result<(), Error> {
+    stream.set_nodelay(true)?;
+    let mut subscription = None;
+    // The error type is tungstenite's.
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| {
+        let subscribed = handshake(request, server).map_err(|(status, msg)| {
+            let mut response = ErrorResponse::new(Some(msg));
+            *response.status_mut() = status;
+            response
+        })?;
+        subscription = Some(subscribed);
+        Ok(response)
+    };
+    let handshake = tokio_tungstenite::accept_hdr_async(stream, callback);
+    let mut ws = timeout(server.timeouts.read, handshake).await??;
+    let mut subscription = subscription.unwrap();
+
+    let mut shutdown = server.shutdown.subscribe();
+    let (code, reason) = loop {
+        tokio::select! {
+            written = subscription.next() => match written {
+                Some(batch) => {
+                    if let Some(json) = to_json(&batch, subscription.symbols.as_ref())? {
+                        timeout(server.timeouts.write, ws.send(Message::text(json))).await??;
+                    }
+                }
+                None => break (CloseCode::Again, "fell behind the live tail"),
+            },
+            // Reading also answers pings and the client's close.
+            message = ws.next() => match message {
+                None => return Ok(()),
+                Some(message) => drop(message?),
+            },
+            // Dropping the guard `wait_for` returns, which mustn't be held
+            // across the sends above.
+            _ = async { drop(shutdown.wait_for(|&stop| stop).await) } => {
+                break (CloseCode::Away, "server shutting down");
+            }
+        }
+    };
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    timeout(server.timeouts.write, ws.close(Some(frame))).await??;
+    Ok(())
+}
+
+/// Checks the handshake `request` and subscribes to the table it names.
+fn handshake(request: &Request, server: &Server) -> Result<Subscription, (StatusCode, String)> {
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, msg);
+    let table = request.uri().path().trim_start_matches('/');
+    if table.is_empty() || table.contains('/') {
+        return Err(bad_request("expected a path of /<table>".into()));
+    }
+    let mut namespace = DEFAULT_NAMESPACE.to_string();
+    let mut symbols = None;
+    let mut token = None;
+    let query = request.uri().query().unwrap_or_default();
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match &*key {
+            "namespace" => namespace = value.into_owned(),
+            "symbols" => symbols = Some(value.split(',').map(str::to_string).collect()),
+            "token" => token = Some(value.into_owned()),
+            _ => return Err(bad_request(format!("unknown parameter {key:?}"))),
+        }
+    }
+
+    let acl = server.tokens.as_ref().zip(token);
+    let acl = acl.and_then(|(tokens, token)| tokens.get(&token));
+    open(server, acl, &namespace, table, symbols)
+}
+
+/// Subscribes a connection with `acl` to `table` of `namespace`, if it may
+/// read it.
+fn open(
+    server: &Server,
+    acl: Option<&Acl>,
+    namespace: &str,
+    table: &str,
+    symbols: Option<HashSet<String>>,
+) -> Result<Subscription, (StatusCode, String)> {
+    let bad_request = |msg: String| (StatusCode::BAD_REQUEST, msg);
+    let permitted = server.tokens.is_none()
+        || acl.is_some_and(|acl| acl.permits(namespace, table, Permission::Read));
+    if !permitted {
+        return Err((StatusCode::FORBIDDEN, "not permitted".into()));
+    }
+    let Some(ns) = server.namespaces.get(namespace) else {
+        let msg = format!("unknown namespace {namespace:?}");
+        return Err((StatusCode::NOT_FOUND, msg));
+    };
+    let schema = ns
+        .db
+        .schema(table)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    if symbols.is_some() && schema.column_with_name(SYMBOL_COL).is_none() {
+        return Err(bad_request(format!("table {table:?} has no symbols")));
+    }
+    let (receiver, bytes) = ns.tail.subscribe(table);
+    Ok(Subscription {
+        symbols,
+        receiver,
+        bytes,
+    })
+}
+
+/// Serves a [`Request::Subscribe`](zola_db_proto::Request::Subscribe) on the
+/// binary protocol: the [`Subscription`] to return, or the response refusing
+/// it.
+pub fn subscribe(
+    server: &Server,
+    acl: Option<&Acl>,
+    namespace: &str,
+    table: &str,
+    symbols: Option<Vec<String>>,
+) -> Result<Subscription, zola_db_proto::Response> {
+    let symbols = symbols.map(|symbols| symbols.into_iter().collect());
+    open(server, acl, namespace, table, symbols).map_err(|(status, msg)| match status {
+        StatusCode::FORBIDDEN => zola_db_proto::Response::Unauthenticated,
+        StatusCode::NOT_FOUND => zola_db_proto::Response::Error(ErrorCode::NotFound, msg),
+        _ => zola_db_proto::Response::Error(ErrorCode::InvalidInput, msg),
+    })
+}
+
+/// Sends the writes of `subscription` on `stream`, as
+/// [`Response::Event`](zola_db_proto::Response::Event)s to request `id`,
+/// until the client closes it. Falling more than [`CAPACITY`] writes or
+/// [`MAX_LAG_BYTES`] behind ends the subscription with an error response, and
+/// the server shutting down with a
+/// [`Response::GoAway`](zola_db_proto::Response::GoAway).
+pub async fn push(
+    stream: &mut Stream,
+    id: u64,
+    mut subscription: Subscription,
+    server: &Server,
+    compression: Compression,
+) -> Result<(), Error> {
+    let mut shutdown = server.shutdown.subscribe();
+    let mut buf = [0; 1];
+    let lagged = || {
+        let msg = "fell behind the subscription".to_string();
+        (
+            id,
+            zola_db_proto::Response::Error(ErrorCode::Unavailable, msg),
+        )
+    };
+    let (id, last) = loop {
+        tokio::select! {
+            written = subscription.next() => match written {
+                Some(batch) => {
+                    let batch = matching(&batch, subscription.symbols.as_ref())?;
+                    if batch.num_rows() > 0 {
+                        let event = zola_db_proto::Response::Event(batch);
+                        let write = zola_db_proto::write_response(stream, id, &event, compression);
+                        timeout(server.timeouts.write, write).await??;
+                    }
+                }
+                None => break lagged(),
+            },
+            // The client sends nothing more, so this ends when it closes.
+            read = stream.read(&mut buf) => match read? {
+                0 => return Ok(()),
+                _ => return Err("request on a subscribed connection".into()),
+            },
+            _ = async { drop(shutdown.wait_for(|&stop| stop).await) } => {
+                break (GOAWAY_ID, zola_db_proto::Response::GoAway);
+            }
+        }
+    };
+    let write = zola_db_proto::write_response(stream, id, &last, compression);
+    timeout(server.timeouts.write, write).await??;
+    Ok(())
+}
+
+/// The rows of `batch` of `symbols`, or all of them if `None`.
+fn matching(
+    batch: &RecordBatch,
+    symbols: Option<&HashSet<String>>,
+) -> Result<RecordBatch, ArrowError> {
+    Ok(match (symbols, batch.column_by_name(SYMBOL_COL)) {
+        (Some(symbols), Some(column)) => {
+            let column = cast(column, &DataType::Utf8)?;
+            let keep: BooleanArray = column
+                .as_string::<i32>()
+                .iter()
+                .map(|symbol| Some(symbol.is_some_and(|s| symbols.contains(s))))
+                .collect();
+            filter_record_batch(batch, &keep)?
+        }
+        _ => batch.clone(),
+    })
+}
+
+/// The rows of `batch` of `symbols` as a JSON array, or `None` if there are
+/// none.
+fn to_json(
+    batch: &RecordBatch,
+    symbols: Option<&HashSet<String>>,
+) -> Result<Option<String>, ArrowError> {
+    let batch = matching(batch, symbols)?;
+    if batch.num_rows() == 0 {
+        return Ok(None);
+    }
+    let mut writer = arrow::json::ArrayWriter::new(Vec::new());
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(Some(
+        String::from_utf8(writer.into_inner()).expect("JSON is UTF-8"),
+    ))
+}